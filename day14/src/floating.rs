@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+// A set of addresses: every address that matches `fixed` outside the `floating` bits.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct AddressPattern {
+    fixed: u128,
+    floating: u128,
+}
+
+impl AddressPattern {
+    pub fn new(address: u128, floating: u128) -> Self {
+        AddressPattern {
+            fixed: address & !floating,
+            floating,
+        }
+    }
+
+    // The addresses in both patterns, if there are any.
+    pub fn intersection(&self, other: &AddressPattern) -> Option<AddressPattern> {
+        let both_fixed = !self.floating & !other.floating;
+        if (self.fixed ^ other.fixed) & both_fixed != 0 {
+            return None;
+        }
+
+        Some(AddressPattern::new(
+            self.fixed | other.fixed,
+            self.floating & other.floating,
+        ))
+    }
+
+    // The number of addresses in the pattern, which wraps to 0 when all 128 bits float.
+    pub fn size(&self) -> u128 {
+        1u128.checked_shl(self.floating.count_ones()).unwrap_or(0)
+    }
+}
+
+// Memory written through floating masks, without expanding them into addresses. Each
+// pattern carries a weight, and the value at an address is the sum of the weights of the
+// patterns containing it. Writing subtracts whatever the new pattern already holds,
+// pattern by pattern, which is inclusion-exclusion over the overlapping writes.
+//
+// Arithmetic wraps, so totals are exact as long as the true total fits in a u128.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolicMemory {
+    weights: HashMap<AddressPattern, u128>,
+}
+
+impl SymbolicMemory {
+    pub fn write(&mut self, pattern: AddressPattern, value: u128) {
+        let mut changes: HashMap<AddressPattern, u128> = HashMap::new();
+
+        for (existing, &weight) in &self.weights {
+            if let Some(overlap) = existing.intersection(&pattern) {
+                let change = changes.entry(overlap).or_insert(0);
+                *change = change.wrapping_sub(weight);
+            }
+        }
+        let change = changes.entry(pattern).or_insert(0);
+        *change = change.wrapping_add(value);
+
+        for (pattern, change) in changes {
+            let weight = self.weights.entry(pattern).or_insert(0);
+            *weight = weight.wrapping_add(change);
+            if *weight == 0 {
+                self.weights.remove(&pattern);
+            }
+        }
+    }
+
+    pub fn sum(&self) -> u128 {
+        self.weights
+            .iter()
+            .fold(0u128, |total, (pattern, &weight)| {
+                total.wrapping_add(weight.wrapping_mul(pattern.size()))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intersection() {
+        let a = AddressPattern::new(0b0100, 0b0011);
+        let b = AddressPattern::new(0b0101, 0b1000);
+        assert_eq!(a.intersection(&b), Some(AddressPattern::new(0b0101, 0)));
+        assert_eq!(a.intersection(&AddressPattern::new(0, 0b1011)), None);
+        assert_eq!(a.size(), 4);
+        assert_eq!(AddressPattern::new(0, u128::MAX).size(), 0);
+    }
+
+    #[test]
+    fn test_overwrites() {
+        let mut memory: SymbolicMemory = Default::default();
+        memory.write(AddressPattern::new(0, 0b111), 10);
+        memory.write(AddressPattern::new(0b001, 0b010), 3);
+        memory.write(AddressPattern::new(0b011, 0), 0);
+        assert_eq!(memory.sum(), 6 * 10 + 3);
+
+        // Writing every address again leaves a single pattern.
+        memory.write(AddressPattern::new(0, 0b111), 1);
+        assert_eq!(memory.sum(), 8);
+        assert_eq!(memory.weights.len(), 1);
+    }
+
+    #[test]
+    fn test_36_floating_bits() {
+        let mut memory: SymbolicMemory = Default::default();
+        memory.write(AddressPattern::new(0, (1 << 36) - 1), 1);
+        memory.write(AddressPattern::new(5, 0), 7);
+        assert_eq!(memory.sum(), (1 << 36) - 1 + 7);
+    }
+}
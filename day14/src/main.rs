@@ -1,26 +1,40 @@
-use scan_fmt::scan_fmt;
+mod floating;
+mod program;
+
+use floating::{AddressPattern, SymbolicMemory};
+use program::{parse_program, DockingInstruction};
 use std::collections::BTreeMap;
+use std::env;
 use std::error::Error;
 use std::fmt;
 use std::str::FromStr;
 
-fn main() -> Result<(), InvalidBitmaskError> {
-    let program: Vec<&str> = include_str!("puzzle_input.txt").lines().collect();
+fn main() -> Result<(), Box<dyn Error>> {
+    let program = parse_program(include_str!("puzzle_input.txt"))?;
 
-    let result = run(&program)?;
+    let result = run(&program);
 
     println!("Part 1 result: {}", result);
 
-    let result = run_part2(&program)?;
+    // Expanding every floating address is slower, but kept to check against.
+    let result = match env::args().nth(1).as_deref() {
+        Some("--expand") => run_part2(&program)?,
+        _ => run_part2_symbolic(&program),
+    };
     println!("Part 2 result: {}", result);
 
     Ok(())
 }
 
+// The decoder chip's word size, in bits.
+const PUZZLE_WIDTH: usize = 36;
+const MAX_WIDTH: usize = 128;
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 enum InvalidBitmaskError {
     InvalidCharacter(char),
     InvalidLength(usize),
+    UnsupportedWidth(usize),
 }
 
 impl fmt::Display for InvalidBitmaskError {
@@ -28,50 +42,74 @@ impl fmt::Display for InvalidBitmaskError {
         match *self {
             InvalidBitmaskError::InvalidCharacter(c) => write!(f, "Invalid character: {}", c),
             InvalidBitmaskError::InvalidLength(l) => write!(f, "Invalid length: {}", l),
+            InvalidBitmaskError::UnsupportedWidth(w) => {
+                write!(f, "Unsupported width: {} (must be 1 to {})", w, MAX_WIDTH)
+            }
         }
     }
 }
 
 impl Error for InvalidBitmaskError {}
 
-struct Bitmask {
-    set_bits: u64,
-    clear_bits: u64,
-}
+// Expanding more floating bits than this would write over a million addresses.
+const MAX_EXPANDED_FLOATING_BITS: usize = 20;
 
-impl Bitmask {
-    pub fn apply(&self, value: u64) -> u64 {
-        (value | self.set_bits) & self.clear_bits
-    }
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum DecoderError {
+    TooManyFloatingBits(usize),
 }
 
-impl Default for Bitmask {
-    fn default() -> Self {
-        Bitmask {
-            set_bits: 0,
-            clear_bits: u64::MAX,
+impl fmt::Display for DecoderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecoderError::TooManyFloatingBits(n) => write!(
+                f,
+                "Too many floating bits to expand: {} (at most {})",
+                n, MAX_EXPANDED_FLOATING_BITS
+            ),
         }
     }
 }
 
-impl FromStr for Bitmask {
-    type Err = InvalidBitmaskError;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let length = s.len();
-        if length != 36 {
+impl Error for DecoderError {}
+
+// All ones in the lowest `width` bits.
+fn word_mask(width: usize) -> u128 {
+    u128::MAX >> (MAX_WIDTH - width)
+}
+
+// A mask as written, before either version of the decoder chip interprets it.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+struct Mask {
+    ones: u128,
+    zeros: u128,
+    floating: u128,
+}
+
+impl Mask {
+    // Masks have exactly one character per bit of a word `width` bits wide.
+    pub fn parse_with_width(s: &str, width: usize) -> Result<Self, InvalidBitmaskError> {
+        if width == 0 || width > MAX_WIDTH {
+            return Err(InvalidBitmaskError::UnsupportedWidth(width));
+        }
+
+        let length = s.chars().count();
+        if length != width {
             return Err(InvalidBitmaskError::InvalidLength(length));
         }
 
-        let mut mask: Bitmask = Default::default();
+        let mut mask: Mask = Default::default();
         for (index, bit) in s.chars().rev().enumerate() {
             match bit {
                 '0' => {
-                    mask.clear_bits ^= 1 << index;
+                    mask.zeros |= 1 << index;
                 }
                 '1' => {
-                    mask.set_bits |= 1 << index;
+                    mask.ones |= 1 << index;
+                }
+                'X' => {
+                    mask.floating |= 1 << index;
                 }
-                'X' => {}
                 other => return Err(InvalidBitmaskError::InvalidCharacter(other)),
             }
         }
@@ -80,25 +118,80 @@ impl FromStr for Bitmask {
     }
 }
 
+impl FromStr for Mask {
+    type Err = InvalidBitmaskError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Mask::parse_with_width(s, PUZZLE_WIDTH)
+    }
+}
+
+struct Bitmask {
+    set_bits: u128,
+    clear_bits: u128,
+}
+
+impl Bitmask {
+    pub fn apply(&self, value: u128) -> u128 {
+        (value | self.set_bits) & self.clear_bits
+    }
+}
+
+impl Default for Bitmask {
+    fn default() -> Self {
+        Bitmask {
+            set_bits: 0,
+            clear_bits: u128::MAX,
+        }
+    }
+}
+
+impl From<Mask> for Bitmask {
+    fn from(mask: Mask) -> Self {
+        Bitmask {
+            set_bits: mask.ones,
+            clear_bits: !mask.zeros,
+        }
+    }
+}
+
+impl FromStr for Bitmask {
+    type Err = InvalidBitmaskError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<Mask>().map(Bitmask::from)
+    }
+}
+
+#[derive(Default)]
 struct Bitmask2 {
-    set_bits: u64,
+    set_bits: u128,
     floating_bits: Vec<usize>,
 }
 
 impl Bitmask2 {
-    pub fn apply(&self, value: u64) -> Vec<u64> {
+    // Every address `apply` would write to, without listing them.
+    pub fn pattern(&self, value: u128) -> AddressPattern {
+        let floating = self.floating_bits.iter().fold(0, |bits, &index| bits | 1 << index);
+        AddressPattern::new(value | self.set_bits, floating)
+    }
+
+    pub fn apply(&self, value: u128) -> Result<Vec<u128>, DecoderError> {
+        let floating = self.floating_bits.len();
+        if floating > MAX_EXPANDED_FLOATING_BITS {
+            return Err(DecoderError::TooManyFloatingBits(floating));
+        }
+
         let seed = value | self.set_bits;
-        let mut outputs = Vec::with_capacity(1 << self.floating_bits.len());
+        let mut outputs = Vec::with_capacity(1 << floating);
         Bitmask2::apply_floating(seed, &self.floating_bits, &mut outputs);
 
-        outputs
+        Ok(outputs)
     }
 
-    fn apply_floating(seed: u64, indices: &[usize], outputs: &mut Vec<u64>) {
+    fn apply_floating(seed: u128, indices: &[usize], outputs: &mut Vec<u128>) {
         if indices.is_empty() {
             outputs.push(seed);
         } else {
-            let mask: u64 = 1 << indices[0];
+            let mask: u128 = 1 << indices[0];
             let bit_set = seed | mask;
             let bit_cleared = bit_set ^ mask;
             if indices.len() > 1 {
@@ -113,11 +206,11 @@ impl Bitmask2 {
     }
 }
 
-impl Default for Bitmask2 {
-    fn default() -> Self {
+impl From<Mask> for Bitmask2 {
+    fn from(mask: Mask) -> Self {
         Bitmask2 {
-            set_bits: 0,
-            floating_bits: Vec::new(),
+            set_bits: mask.ones,
+            floating_bits: (0..MAX_WIDTH).filter(|&i| mask.floating & 1 << i != 0).collect(),
         }
     }
 }
@@ -125,68 +218,68 @@ impl Default for Bitmask2 {
 impl FromStr for Bitmask2 {
     type Err = InvalidBitmaskError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let length = s.len();
-        if length != 36 {
-            return Err(InvalidBitmaskError::InvalidLength(length));
-        }
+        s.parse::<Mask>().map(Bitmask2::from)
+    }
+}
 
-        let mut mask: Bitmask2 = Default::default();
-        for (index, bit) in s.chars().rev().enumerate() {
-            match bit {
-                '0' => {}
-                '1' => {
-                    mask.set_bits |= 1 << index;
-                }
-                'X' => {
-                    mask.floating_bits.push(index);
-                }
-                other => return Err(InvalidBitmaskError::InvalidCharacter(other)),
+// Totals in all three decoders wrap around at 2^128, so they agree even when the true
+// total doesn't fit in a u128.
+fn run(program: &[DockingInstruction]) -> u128 {
+    let mut memory = BTreeMap::new();
+    let mut mask: Bitmask = Default::default();
+
+    for instruction in program {
+        match *instruction {
+            DockingInstruction::SetMask(m) => mask = m.into(),
+            DockingInstruction::Write { address, value } => {
+                memory.insert(address, mask.apply(value));
             }
         }
-
-        Ok(mask)
     }
-}
 
+    memory.values().fold(0, |total: u128, &value| total.wrapping_add(value))
+}
 
-fn run(program: &[&str]) -> Result<u64, InvalidBitmaskError> {
+fn run_part2(program: &[DockingInstruction]) -> Result<u128, DecoderError> {
     let mut memory = BTreeMap::new();
-    let mut mask: Bitmask = Default::default();
+    let mut mask: Bitmask2 = Default::default();
 
     for instruction in program {
-        if let Ok(m) = scan_fmt!(instruction, "mask = {}", String) {
-            mask = m.parse()?;
-        } else if let Ok((address, value)) = scan_fmt!(instruction, "mem[{d}] = {d}", u64, u64) {
-            memory.insert(address, mask.apply(value));
+        match *instruction {
+            DockingInstruction::SetMask(m) => mask = m.into(),
+            DockingInstruction::Write { address, value } => {
+                let addresses = mask.apply(address)?;
+                for address in addresses {
+                    memory.insert(address, value);
+                }
+            }
         }
     }
 
-    let total = memory.values().sum();
-    Ok(total)
+    Ok(memory.values().fold(0, |total: u128, &value| total.wrapping_add(value)))
 }
 
-fn run_part2(program: &[&str]) -> Result<u64, InvalidBitmaskError> {
-    let mut memory = BTreeMap::new();
+fn run_part2_symbolic(program: &[DockingInstruction]) -> u128 {
+    let mut memory: SymbolicMemory = Default::default();
     let mut mask: Bitmask2 = Default::default();
 
     for instruction in program {
-        if let Ok(m) = scan_fmt!(instruction, "mask = {}", String) {
-            mask = m.parse()?;
-        } else if let Ok((address, value)) = scan_fmt!(instruction, "mem[{d}] = {d}", u64, u64) {
-            let addresses = mask.apply(address);
-            for address in addresses {
-                memory.insert(address, value);
+        match *instruction {
+            DockingInstruction::SetMask(m) => mask = m.into(),
+            DockingInstruction::Write { address, value } => {
+                memory.write(mask.pattern(address), value);
             }
         }
     }
 
-    let total = memory.values().sum();
-    Ok(total)
+    memory.sum()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rng::Rng;
+    use program::parse_program_with_width;
 
     #[test]
     fn test_bitmask() {
@@ -196,32 +289,127 @@ mod tests {
 
     #[test]
     fn test_part1() {
-        let program: Vec<&str> = "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X
+        let program = parse_program("mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X
         mem[8] = 11
         mem[7] = 101
-        mem[8] = 0"
-            .lines()
-            .collect();
+        mem[8] = 0")
+            .unwrap();
         let result = run(&program);
 
-        assert_eq!(result, Ok(165));
+        assert_eq!(result, 165);
     }
 
     #[test]
     fn test_bitmask2() {
         let mask: Bitmask2 = "000000000000000000000000000000X1001X".parse().unwrap();
-        let mut values = mask.apply(42);
+        let mut values = mask.apply(42).unwrap();
         values.sort_unstable();
         assert_eq!(values, vec![26, 27, 58, 59]);
     }
 
     #[test]
     fn test_part2() {
-        let program: Vec<&str> = "mask = 000000000000000000000000000000X1001X
+        let program = parse_program("mask = 000000000000000000000000000000X1001X
         mem[42] = 100
         mask = 00000000000000000000000000000000X0XX
-        mem[26] = 1".lines().collect();
+        mem[26] = 1").unwrap();
         let result = run_part2(&program);
         assert_eq!(result, Ok(208));
+        assert_eq!(run_part2_symbolic(&program), 208);
+    }
+
+    #[test]
+    fn test_symbolic_matches_expanded() {
+        let program = parse_program(include_str!("puzzle_input.txt")).unwrap();
+        assert_eq!(Ok(run_part2_symbolic(&program)), run_part2(&program));
+
+        // Short words, so that masks overlap a lot.
+        let mut rng = Rng::new(14);
+        for _ in 0..50 {
+            let mut lines = Vec::new();
+            for _ in 0..20 {
+                if rng.below(3) == 0 {
+                    let mask: String = (0..6).map(|_| ['0', '1', 'X', 'X'][rng.below(4)]).collect();
+                    lines.push(format!("mask = {}", mask));
+                } else {
+                    lines.push(format!("mem[{}] = {}", rng.below(64), rng.below(64)));
+                }
+            }
+            let program = parse_program_with_width(&lines.join("\n"), 6).unwrap();
+            assert_eq!(Ok(run_part2_symbolic(&program)), run_part2(&program));
+        }
+    }
+
+    #[test]
+    fn test_symbolic_with_many_floating_bits() {
+        let program = parse_program(
+            "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+            mem[0] = 1
+            mask = 00000000000000000000000000000000X000
+            mem[0] = 3",
+        )
+        .unwrap();
+        assert_eq!(run_part2_symbolic(&program), (1 << 36) - 2 + 6);
+    }
+
+    #[test]
+    fn test_mask_widths() {
+        let mask = Mask::parse_with_width("1X0", 3).unwrap();
+        assert_eq!(Bitmask::from(mask).apply(3), 6);
+        assert_eq!(
+            Mask::parse_with_width("1X0", 4).err(),
+            Some(InvalidBitmaskError::InvalidLength(3))
+        );
+        assert_eq!(
+            "1X0".parse::<Bitmask>().err(),
+            Some(InvalidBitmaskError::InvalidLength(3))
+        );
+        assert_eq!(
+            Mask::parse_with_width(&"X".repeat(129), 129).err(),
+            Some(InvalidBitmaskError::UnsupportedWidth(129))
+        );
+        assert_eq!(
+            Mask::parse_with_width("", 0).err(),
+            Some(InvalidBitmaskError::UnsupportedWidth(0))
+        );
+    }
+
+    #[test]
+    fn test_128_bit_words() {
+        let mask = format!("1{}", "X".repeat(127));
+        let text = format!("mask = {}\nmem[1] = 5", mask);
+        let program = parse_program_with_width(&text, 128).unwrap();
+        assert_eq!(run(&program), (1 << 127) + 5);
+
+        let mask = format!("{}X1", "0".repeat(126));
+        let text = format!("mask = {}\nmem[{}] = 3", mask, u128::MAX - 1);
+        let program = parse_program_with_width(&text, 128).unwrap();
+        // Writes to u128::MAX and u128::MAX - 2.
+        assert_eq!(run_part2(&program), Ok(6));
+        assert_eq!(run_part2_symbolic(&program), 6);
+    }
+
+    #[test]
+    fn test_too_many_floating_bits_to_expand() {
+        let text = format!("mask = {}\nmem[0] = 1", "X".repeat(64));
+        let program = parse_program_with_width(&text, 64).unwrap();
+        assert_eq!(run_part2(&program), Err(DecoderError::TooManyFloatingBits(64)));
+        assert_eq!(run_part2_symbolic(&program), 1 << 64);
+    }
+
+    #[test]
+    fn test_totals_wrap_consistently() {
+        // Part 1 needs a mask that keeps the values, and part 2 one that keeps addresses.
+        let program = |mask: char| {
+            let text = format!(
+                "mask = {mask}\nmem[1] = {big}\nmem[2] = {big}\nmem[3] = 5",
+                mask = mask.to_string().repeat(128),
+                big = 1u128 << 127
+            );
+            parse_program_with_width(&text, 128).unwrap()
+        };
+        assert_eq!(run(&program('X')), 5);
+        assert_eq!(run_part2(&program('0')), Ok(5));
+        assert_eq!(run_part2_symbolic(&program('0')), 5);
     }
 }
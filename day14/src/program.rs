@@ -0,0 +1,198 @@
+use crate::{word_mask, InvalidBitmaskError, Mask, MAX_WIDTH, PUZZLE_WIDTH};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DockingInstruction {
+    SetMask(Mask),
+    Write { address: u128, value: u128 },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ParseError {
+    InvalidMask(InvalidBitmaskError),
+    InvalidAddress(String),
+    InvalidValue(String),
+    UnknownInstruction(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::InvalidMask(e) => write!(f, "Invalid mask: {}", e),
+            ParseError::InvalidAddress(a) => write!(f, "Invalid address: {}", a),
+            ParseError::InvalidValue(v) => write!(f, "Invalid value: {}", v),
+            ParseError::UnknownInstruction(i) => write!(f, "Unknown instruction: {}", i),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+// A line of a program that couldn't be parsed. Lines are numbered from 1.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ProgramError {
+    pub line: usize,
+    pub error: ParseError,
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.error)
+    }
+}
+
+impl Error for ProgramError {}
+
+impl DockingInstruction {
+    // Addresses and values must fit in a word `width` bits wide.
+    pub fn parse_with_width(s: &str, width: usize) -> Result<Self, ParseError> {
+        if width == 0 || width > MAX_WIDTH {
+            return Err(ParseError::InvalidMask(InvalidBitmaskError::UnsupportedWidth(width)));
+        }
+        let s = s.trim();
+
+        if let Some(mask) = s.strip_prefix("mask = ") {
+            return Mask::parse_with_width(mask, width)
+                .map(DockingInstruction::SetMask)
+                .map_err(ParseError::InvalidMask);
+        }
+
+        let (address, value) = s
+            .strip_prefix("mem[")
+            .and_then(|rest| rest.split_once("] = "))
+            .ok_or_else(|| ParseError::UnknownInstruction(s.to_string()))?;
+        let fits = |n: &u128| n & !word_mask(width) == 0;
+        let address: u128 = address
+            .parse()
+            .ok()
+            .filter(fits)
+            .ok_or_else(|| ParseError::InvalidAddress(address.to_string()))?;
+        let value: u128 = value
+            .parse()
+            .ok()
+            .filter(fits)
+            .ok_or_else(|| ParseError::InvalidValue(value.to_string()))?;
+
+        Ok(DockingInstruction::Write { address, value })
+    }
+}
+
+impl FromStr for DockingInstruction {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DockingInstruction::parse_with_width(s, PUZZLE_WIDTH)
+    }
+}
+
+pub fn parse_program(text: &str) -> Result<Vec<DockingInstruction>, ProgramError> {
+    parse_program_with_width(text, PUZZLE_WIDTH)
+}
+
+// Blank lines are skipped, and the first bad line is reported.
+pub fn parse_program_with_width(
+    text: &str,
+    width: usize,
+) -> Result<Vec<DockingInstruction>, ProgramError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            DockingInstruction::parse_with_width(line, width).map_err(|error| ProgramError {
+                line: index + 1,
+                error,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_instructions() {
+        let instruction: DockingInstruction = "mem[8] = 11".parse().unwrap();
+        assert_eq!(
+            instruction,
+            DockingInstruction::Write {
+                address: 8,
+                value: 11
+            }
+        );
+
+        let mask = "XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X";
+        let instruction: DockingInstruction = format!("mask = {}", mask).parse().unwrap();
+        assert_eq!(instruction, DockingInstruction::SetMask(mask.parse().unwrap()));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            "mask = 1X0".parse::<DockingInstruction>(),
+            Err(ParseError::InvalidMask(InvalidBitmaskError::InvalidLength(3)))
+        );
+        assert_eq!(
+            "mem[-1] = 5".parse::<DockingInstruction>(),
+            Err(ParseError::InvalidAddress("-1".to_string()))
+        );
+        assert_eq!(
+            "mem[1] = five".parse::<DockingInstruction>(),
+            Err(ParseError::InvalidValue("five".to_string()))
+        );
+        assert_eq!(
+            "mem 1 = 5".parse::<DockingInstruction>(),
+            Err(ParseError::UnknownInstruction("mem 1 = 5".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_words_must_fit_width() {
+        assert_eq!(
+            DockingInstruction::parse_with_width("mem[9] = 5", 3),
+            Err(ParseError::InvalidAddress("9".to_string()))
+        );
+        assert_eq!(
+            DockingInstruction::parse_with_width("mem[1] = 101", 3),
+            Err(ParseError::InvalidValue("101".to_string()))
+        );
+        assert_eq!(
+            "mem[1] = 68719476736".parse::<DockingInstruction>(),
+            Err(ParseError::InvalidValue("68719476736".to_string()))
+        );
+        assert_eq!(
+            DockingInstruction::parse_with_width("mem[7] = 7", 3),
+            Ok(DockingInstruction::Write {
+                address: 7,
+                value: 7
+            })
+        );
+    }
+
+    #[test]
+    fn test_unsupported_widths() {
+        for &width in &[0, 129] {
+            assert_eq!(
+                DockingInstruction::parse_with_width("mem[0] = 0", width),
+                Err(ParseError::InvalidMask(InvalidBitmaskError::UnsupportedWidth(width)))
+            );
+        }
+    }
+
+    #[test]
+    fn test_program_errors_have_line_numbers() {
+        let program = parse_program("mem[1] = 2\n\n    mem[3] = 4\nmem[5] = x\nnop");
+        let error = program.unwrap_err();
+        assert_eq!(
+            error,
+            ProgramError {
+                line: 4,
+                error: ParseError::InvalidValue("x".to_string())
+            }
+        );
+        assert_eq!(error.to_string(), "Line 4: Invalid value: x");
+
+        assert_eq!(parse_program("mem[1] = 2\n\n  mem[3] = 4\n").unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,58 @@
+use day17::dense::Dense;
+use day17::n_d::{next_generation, read_cubes, Coordinate};
+use std::collections::HashSet;
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+
+const CYCLES: usize = 6;
+const LAYOUT: &str = include_str!("../src/puzzle_input.txt");
+
+// Runs the puzzle input for its full six cycles in each dimension, on the sparse HashSet
+// grid and on the bit-packed dense grid, and compares the time taken and the memory held
+// by the final grid. Six dimensions take about a minute, so they're left out.
+fn main() {
+    compare::<3>();
+    compare::<4>();
+    compare::<5>();
+}
+
+// A hashbrown set holds one control byte alongside each bucket.
+fn set_memory<const D: usize>(grid: &HashSet<Coordinate<D>>) -> usize {
+    grid.capacity() * (size_of::<Coordinate<D>>() + 1)
+}
+
+fn compare<const D: usize>() {
+    let seed = read_cubes::<D>(LAYOUT);
+
+    let (sparse_time, sparse) = time(seed.clone(), next_generation);
+    let (dense_time, dense) = time(Dense::new(&seed, CYCLES), Dense::next_generation);
+    assert_eq!(sparse.len(), dense.len());
+
+    println!(
+        "{}D sparse {:>10.3?} {:>10} bytes | dense {:>10.3?} {:>10} bytes | {} active",
+        D,
+        sparse_time,
+        set_memory(&sparse),
+        dense_time,
+        dense.memory(),
+        dense.len()
+    );
+    println!(
+        "{}D dense is {:.1}x faster in {:.2}x the memory",
+        D,
+        sparse_time.as_secs_f64() / dense_time.as_secs_f64(),
+        dense.memory() as f64 / set_memory(&sparse) as f64
+    );
+}
+
+fn time<T, S>(seed: T, step: S) -> (Duration, T)
+where
+    S: Fn(&T) -> T,
+{
+    let start = Instant::now();
+    let mut grid = seed;
+    for _ in 0..CYCLES {
+        grid = step(&grid);
+    }
+    (start.elapsed(), grid)
+}
@@ -0,0 +1,58 @@
+use day17::n_d::{next_generation, next_generation_by_scanning, read_cubes, Coordinate};
+use day17::symmetric::Mirrored;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+const CYCLES: usize = 3;
+const LAYOUT: &str = ".#.
+    ..#
+    ###";
+
+// Times a few cycles of the example in each dimension with each way of computing the
+// next generation. Scanning the bounding box gets very slow from five dimensions up, so
+// the run is kept short.
+fn main() {
+    compare::<3>();
+    compare::<4>();
+    compare::<5>();
+    compare::<6>();
+}
+
+fn compare<const D: usize>() {
+    let seed = read_cubes::<D>(LAYOUT);
+    let len = |grid: &HashSet<Coordinate<D>>| grid.len();
+    let scanning = time(
+        &format!("{}D scanning", D),
+        seed.clone(),
+        next_generation_by_scanning,
+        len,
+    );
+    let counting = time(&format!("{}D counting", D), seed.clone(), next_generation, len);
+    let mirrored = time(
+        &format!("{}D mirrored", D),
+        Mirrored::new(&seed),
+        Mirrored::next_generation,
+        Mirrored::len,
+    );
+    println!(
+        "{}D speedup over scanning: counting {:.1}x, mirrored {:.1}x",
+        D,
+        scanning.as_secs_f64() / counting.as_secs_f64(),
+        scanning.as_secs_f64() / mirrored.as_secs_f64()
+    );
+}
+
+fn time<T, S, L>(name: &str, seed: T, step: S, len: L) -> Duration
+where
+    S: Fn(&T) -> T,
+    L: Fn(&T) -> usize,
+{
+    let start = Instant::now();
+    let mut grid = seed;
+    for _ in 0..CYCLES {
+        grid = step(&grid);
+    }
+    let elapsed = start.elapsed();
+    println!("{:<14} {:>10.3?} for {} cycles, {} active", name, elapsed, CYCLES, len(&grid));
+    elapsed
+}
@@ -0,0 +1,141 @@
+use crate::n_d::Coordinate;
+
+// An axis-aligned box of cubes, including both corners. A box with `min` greater than
+// `max` on any axis is empty.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BoundingBox<const D: usize> {
+    pub min: Coordinate<D>,
+    pub max: Coordinate<D>,
+}
+
+impl<const D: usize> BoundingBox<D> {
+    pub fn new(min: Coordinate<D>, max: Coordinate<D>) -> Self {
+        BoundingBox { min, max }
+    }
+
+    // The smallest box containing every cube, or `None` if there are none.
+    pub fn around<'a, I>(cubes: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = &'a Coordinate<D>>,
+    {
+        let mut cubes = cubes.into_iter();
+        let first = *cubes.next()?;
+        let mut bounds = BoundingBox::new(first, first);
+
+        for cube in cubes {
+            for (axis, &c) in cube.iter().enumerate() {
+                bounds.min[axis] = bounds.min[axis].min(c);
+                bounds.max[axis] = bounds.max[axis].max(c);
+            }
+        }
+
+        Some(bounds)
+    }
+
+    // Grows the box by `by` cubes on every side.
+    pub fn expand(&self, by: i32) -> Self {
+        BoundingBox {
+            min: self.min.map(|m| m - by),
+            max: self.max.map(|m| m + by),
+        }
+    }
+
+    pub fn contains(&self, cube: &Coordinate<D>) -> bool {
+        (0..D).all(|axis| self.min[axis] <= cube[axis] && cube[axis] <= self.max[axis])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..D).any(|axis| self.min[axis] > self.max[axis])
+    }
+
+    // The number of cubes in the box.
+    pub fn volume(&self) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        (0..D)
+            .map(|axis| (self.max[axis] - self.min[axis] + 1) as usize)
+            .product()
+    }
+
+    pub fn iter(&self) -> Cells<D> {
+        Cells {
+            bounds: *self,
+            next: if self.is_empty() { None } else { Some(self.min) },
+        }
+    }
+}
+
+impl<const D: usize> IntoIterator for BoundingBox<D> {
+    type Item = Coordinate<D>;
+    type IntoIter = Cells<D>;
+
+    fn into_iter(self) -> Cells<D> {
+        self.iter()
+    }
+}
+
+// Every cube in a box, varying the last axis fastest.
+pub struct Cells<const D: usize> {
+    bounds: BoundingBox<D>,
+    next: Option<Coordinate<D>>,
+}
+
+impl<const D: usize> Iterator for Cells<D> {
+    type Item = Coordinate<D>;
+
+    fn next(&mut self) -> Option<Coordinate<D>> {
+        let current = self.next?;
+        let mut following = current;
+        self.next = None;
+
+        for axis in (0..D).rev() {
+            if following[axis] < self.bounds.max[axis] {
+                following[axis] += 1;
+                self.next = Some(following);
+                break;
+            }
+            following[axis] = self.bounds.min[axis];
+        }
+
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_do_not_include_the_origin() {
+        let cubes = [[3, 5, 1], [4, 7, 1], [6, 5, 2]];
+        let bounds = BoundingBox::around(&cubes).unwrap();
+        assert_eq!(BoundingBox::new([3, 5, 1], [6, 7, 2]), bounds);
+        assert!(!bounds.contains(&[0, 0, 0]));
+        assert!(bounds.contains(&[5, 6, 2]));
+        assert_eq!(24, bounds.volume());
+        assert_eq!(None, BoundingBox::<3>::around(&[]));
+    }
+
+    #[test]
+    fn expands_on_every_side() {
+        let bounds = BoundingBox::new([0, 0], [0, 0]).expand(1);
+        assert_eq!(BoundingBox::new([-1, -1], [1, 1]), bounds);
+        assert_eq!(9, bounds.volume());
+        assert!(bounds.expand(-2).is_empty());
+    }
+
+    #[test]
+    fn iterates_every_cube() {
+        let all: Vec<[i32; 2]> = BoundingBox::new([0, 5], [1, 6]).into_iter().collect();
+        assert_eq!(vec![[0, 5], [0, 6], [1, 5], [1, 6]], all);
+
+        let cube = BoundingBox::new([-1; 4], [1; 4]);
+        assert_eq!(cube.volume(), cube.iter().count());
+        assert!(cube.iter().all(|c| cube.contains(&c)));
+
+        let empty = BoundingBox::new([1, 0], [0, 0]);
+        assert_eq!(0, empty.volume());
+        assert_eq!(0, empty.iter().count());
+    }
+}
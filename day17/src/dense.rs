@@ -0,0 +1,232 @@
+use crate::bounding_box::BoundingBox;
+use crate::n_d::Coordinate;
+use std::collections::HashSet;
+
+// Conway Cubes on a fixed grid with one bit per cube. Active cubes spread by at most one
+// cube per cycle, so when the number of cycles is known up front, the seed's bounding box
+// grown by that many on every side holds every cube that can ever be active.
+//
+// The grid is stored as rows along x, packed 64 cubes to a word, and rows are ordered by
+// their remaining coordinates with the last axis fastest. Neighbours are counted a whole
+// word at a time, in bit-sliced counters.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Dense<const D: usize> {
+    bounds: BoundingBox<D>,
+    words_per_row: usize,
+    bits: Vec<u64>,
+    cycles_left: usize,
+}
+
+// Counts to 3 in `ones` and `twos`, with `many` set from 4 up.
+#[derive(Debug, Clone, Copy, Default)]
+struct Counter {
+    ones: u64,
+    twos: u64,
+    many: u64,
+}
+
+impl Counter {
+    fn add(&mut self, bits: u64) {
+        let carry = self.ones & bits;
+        self.ones ^= bits;
+        self.many |= self.twos & carry;
+        self.twos ^= carry;
+    }
+
+    // Active next time round: three neighbours, or two and already active.
+    fn survivors(&self, active: u64) -> u64 {
+        !self.many & self.twos & (self.ones | active)
+    }
+}
+
+impl<const D: usize> Dense<D> {
+    pub fn new(seed: &HashSet<Coordinate<D>>, cycles: usize) -> Self {
+        let seed_bounds = BoundingBox::around(seed).unwrap_or(BoundingBox::new([0; D], [0; D]));
+        let bounds = seed_bounds.expand(cycles as i32);
+        let width = (bounds.max[0] - bounds.min[0] + 1) as usize;
+        let words_per_row = (width + 63) / 64;
+        let rows = bounds.volume() / width;
+
+        let mut grid = Dense {
+            bounds,
+            words_per_row,
+            bits: vec![0; rows * words_per_row],
+            cycles_left: cycles,
+        };
+        for cube in seed {
+            let (word, bit) = grid.locate(cube);
+            grid.bits[word] |= bit;
+        }
+        grid
+    }
+
+    fn row_index(&self, cube: &Coordinate<D>) -> usize {
+        (1..D).fold(0, |index, axis| {
+            let extent = (self.bounds.max[axis] - self.bounds.min[axis] + 1) as usize;
+            index * extent + (cube[axis] - self.bounds.min[axis]) as usize
+        })
+    }
+
+    fn locate(&self, cube: &Coordinate<D>) -> (usize, u64) {
+        let x = (cube[0] - self.bounds.min[0]) as usize;
+        let word = self.row_index(cube) * self.words_per_row + x / 64;
+        (word, 1 << (x % 64))
+    }
+
+    // The first cube of every row, in the order the rows are stored.
+    fn row_starts(&self) -> BoundingBox<D> {
+        let mut rows = self.bounds;
+        rows.max[0] = rows.min[0];
+        rows
+    }
+
+    fn row(&self, index: usize) -> &[u64] {
+        &self.bits[index * self.words_per_row..(index + 1) * self.words_per_row]
+    }
+
+    pub fn contains(&self, cube: &Coordinate<D>) -> bool {
+        if !self.bounds.contains(cube) {
+            return false;
+        }
+        let (word, bit) = self.locate(cube);
+        self.bits[word] & bit != 0
+    }
+
+    // Panics if run for more cycles than the grid was sized for.
+    pub fn next_generation(&self) -> Self {
+        assert!(
+            self.cycles_left > 0,
+            "The grid was only sized for the cycles given to Dense::new"
+        );
+
+        let width = (self.bounds.max[0] - self.bounds.min[0] + 1) as usize;
+        let last_word_mask = match width % 64 {
+            0 => !0,
+            used => (1 << used) - 1,
+        };
+        let rows = self.row_starts();
+        let mut bits = vec![0; self.bits.len()];
+        let mut counters = vec![Counter::default(); self.words_per_row];
+
+        for (index, start) in rows.iter().enumerate() {
+            counters.iter_mut().for_each(|c| *c = Counter::default());
+
+            let mut neighbour_rows = BoundingBox::new(start, start).expand(1);
+            neighbour_rows.min[0] = start[0];
+            neighbour_rows.max[0] = start[0];
+
+            for neighbour in neighbour_rows.into_iter().filter(|r| rows.contains(r)) {
+                let is_self = neighbour == start;
+                let row = self.row(self.row_index(&neighbour));
+
+                for (i, counter) in counters.iter_mut().enumerate() {
+                    let centre = row[i];
+                    let before = if i > 0 { row[i - 1] } else { 0 };
+                    let after = row.get(i + 1).copied().unwrap_or(0);
+
+                    // Bit b of each shifted word holds the cube at b - 1 and b + 1.
+                    counter.add((centre << 1) | (before >> 63));
+                    counter.add((centre >> 1) | (after << 63));
+                    if !is_self {
+                        counter.add(centre);
+                    }
+                }
+            }
+
+            let current = self.row(index);
+            let next = &mut bits[index * self.words_per_row..(index + 1) * self.words_per_row];
+            for ((word, counter), &active) in next.iter_mut().zip(&counters).zip(current) {
+                *word = counter.survivors(active);
+            }
+            next[self.words_per_row - 1] &= last_word_mask;
+        }
+
+        Dense {
+            bounds: self.bounds,
+            words_per_row: self.words_per_row,
+            bits,
+            cycles_left: self.cycles_left - 1,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&word| word == 0)
+    }
+
+    // The bytes held by the grid itself.
+    pub fn memory(&self) -> usize {
+        self.bits.len() * std::mem::size_of::<u64>()
+    }
+
+    pub fn cubes(&self) -> HashSet<Coordinate<D>> {
+        self.bounds.iter().filter(|cube| self.contains(cube)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::n_d::{next_generation, read_cubes};
+
+    const EXAMPLE: &str = ".#.
+        ..#
+        ###";
+
+    fn agrees_with_sparse<const D: usize>(layout: &str, cycles: usize) {
+        let mut sparse = read_cubes::<D>(layout);
+        let mut dense = Dense::new(&sparse, cycles);
+        assert_eq!(sparse, dense.cubes());
+
+        for _ in 0..cycles {
+            sparse = next_generation(&sparse);
+            dense = dense.next_generation();
+            assert_eq!(sparse.len(), dense.len());
+            assert_eq!(sparse, dense.cubes());
+        }
+    }
+
+    #[test]
+    fn matches_sparse_simulation() {
+        agrees_with_sparse::<3>(EXAMPLE, 4);
+        agrees_with_sparse::<4>(EXAMPLE, 3);
+    }
+
+    #[test]
+    fn carries_across_words() {
+        // The lone cube stretches rows past 64 cubes, and the blinker sits across the
+        // boundary between their first two words once the margin is added.
+        let layout = |dots| format!("#{}###", ".".repeat(dots));
+        agrees_with_sparse::<2>(&layout(58), 3);
+        agrees_with_sparse::<3>(&format!("{}\n{}", layout(59), layout(59)), 2);
+    }
+
+    #[test]
+    fn example_counts() {
+        let mut three = Dense::new(&read_cubes::<3>(EXAMPLE), 6);
+        let mut four = Dense::new(&read_cubes::<4>(EXAMPLE), 6);
+        for _ in 0..6 {
+            three = three.next_generation();
+            four = four.next_generation();
+        }
+        assert_eq!(112, three.len());
+        assert_eq!(848, four.len());
+    }
+
+    #[test]
+    #[should_panic(expected = "only sized for")]
+    fn stops_after_the_cycles_given() {
+        let grid = Dense::new(&read_cubes::<3>(EXAMPLE), 1);
+        grid.next_generation().next_generation();
+    }
+
+    #[test]
+    fn empty_seed() {
+        let grid = Dense::<3>::new(&HashSet::new(), 2);
+        assert!(grid.is_empty());
+        assert!(grid.next_generation().is_empty());
+    }
+}
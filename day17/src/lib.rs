@@ -1,2 +1,8 @@
-pub mod three_d;
+pub mod bounding_box;
+pub mod dense;
 pub mod four_d;
+pub mod n_d;
+pub mod render;
+pub mod rules;
+pub mod symmetric;
+pub mod three_d;
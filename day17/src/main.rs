@@ -1,39 +1,167 @@
-use day17::{three_d, four_d};
+use day17::n_d::{next_generation, read_cubes};
+use day17::render::render_slices;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read};
 
-fn main() {
-    let layout = ".##..#.#
-    ##.#...#
-    ##.#.##.
-    ..#..###
-    ####.#..
-    ...##..#
-    #.#####.
-    #.#.##.#";
+const USAGE: &str = "Usage: day17 [--dimensions N] [--cycles N] [--print] [FILE | -]
 
-    let cycles = 6;
+Reads the starting layout from FILE, or from stdin given -, or else uses the puzzle input.
+Without --dimensions, runs in both 3 and 4 dimensions. --print shows every slice of the
+grid after each cycle.";
 
+#[derive(Debug, Clone, PartialEq)]
+struct Options {
+    dimensions: Vec<usize>,
+    cycles: usize,
+    print: bool,
+    input: Option<String>,
+    help: bool,
+}
 
-    let mut grid = three_d::read_cubes(layout);  
-    for _ in 0..cycles {
-        let next = three_d::next_generation(&grid);
-        grid = next;
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        dimensions: vec![3, 4],
+        cycles: 6,
+        print: false,
+        input: None,
+        help: false,
+    };
+
+    let number = |flag: &str, value: Option<String>| -> Result<usize, String> {
+        let value = value.ok_or(format!("{} needs a value", flag))?;
+        value
+            .parse()
+            .map_err(|_| format!("{} needs a number, not '{}'", flag, value))
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dimensions" | "-d" => {
+                let d = number(&arg, args.next())?;
+                if !(2..=6).contains(&d) {
+                    return Err(format!("Can't simulate {} dimensions, only 2 to 6", d));
+                }
+                options.dimensions = vec![d];
+            }
+            "--cycles" | "-c" => options.cycles = number(&arg, args.next())?,
+            "--print" | "-p" => options.print = true,
+            "--help" | "-h" => options.help = true,
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("Unknown option '{}'\n\n{}", arg, USAGE))
+            }
+            _ if options.input.is_some() => return Err(format!("Unexpected '{}'", arg)),
+            _ => options.input = Some(arg),
+        }
     }
 
-    println!(
-        "3D: After {} cycles there are {} active cubes.",
-        cycles,
-        grid.len()
-    );
+    Ok(options)
+}
 
-    let mut grid = four_d::read_cubes(layout);
-    for _ in 0..cycles {
-        let next = four_d::next_generation(&grid);
-        grid = next;
+fn read_layout(input: &Option<String>) -> io::Result<String> {
+    match input.as_deref() {
+        None => Ok(include_str!("puzzle_input.txt").to_string()),
+        Some("-") => {
+            let mut layout = String::new();
+            io::stdin().read_to_string(&mut layout)?;
+            Ok(layout)
+        }
+        Some(path) => fs::read_to_string(path),
+    }
+}
+
+fn simulate<const D: usize>(layout: &str, cycles: usize, print: bool) {
+    let mut grid = read_cubes::<D>(layout);
+    if print {
+        println!("Before any cycles:\n\n{}", render_slices(&grid));
+    }
+
+    for cycle in 1..=cycles {
+        grid = next_generation(&grid);
+        if print {
+            let plural = if cycle == 1 { "" } else { "s" };
+            println!("After {} cycle{}:\n\n{}", cycle, plural, render_slices(&grid));
+        }
     }
 
     println!(
-        "4D: After {} cycles there are {} active cubes.",
+        "{}D: After {} cycles there are {} active cubes.",
+        D,
         cycles,
         grid.len()
     );
-}
\ No newline at end of file
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(2);
+        }
+    };
+    if options.help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let layout = read_layout(&options.input)?;
+
+    for &dimensions in &options.dimensions {
+        match dimensions {
+            2 => simulate::<2>(&layout, options.cycles, options.print),
+            3 => simulate::<3>(&layout, options.cycles, options.print),
+            4 => simulate::<4>(&layout, options.cycles, options.print),
+            5 => simulate::<5>(&layout, options.cycles, options.print),
+            6 => simulate::<6>(&layout, options.cycles, options.print),
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn defaults_to_the_puzzle() {
+        let options = parse(&[]).unwrap();
+        assert_eq!(vec![3, 4], options.dimensions);
+        assert_eq!(6, options.cycles);
+        assert!(!options.print);
+        assert_eq!(None, options.input);
+        assert!(!options.help);
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = parse(&["-d", "5", "--cycles", "2", "--print", "layout.txt"]).unwrap();
+        assert_eq!(
+            Options {
+                dimensions: vec![5],
+                cycles: 2,
+                print: true,
+                input: Some("layout.txt".to_string()),
+                help: false,
+            },
+            options
+        );
+        assert_eq!(Some("-".to_string()), parse(&["-"]).unwrap().input);
+        assert!(parse(&["--print", "-h"]).unwrap().help);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse(&["--dimensions", "7"]).is_err());
+        assert!(parse(&["--cycles", "many"]).is_err());
+        assert!(parse(&["--cycles"]).is_err());
+        assert!(parse(&["--colour"]).is_err());
+        assert!(parse(&["a.txt", "b.txt"]).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use crate::bounding_box::BoundingBox;
+use crate::rules::Rule;
+use std::collections::{HashMap, HashSet};
+pub type Coordinate<const D: usize> = [i32; D];
+
+// Seeds the layout into the slice where every coordinate after x and y is zero.
+pub fn read_cubes<const D: usize>(cubes: &str) -> HashSet<Coordinate<D>> {
+    assert!(D >= 2, "The layout needs at least two dimensions");
+    let mut grid = HashSet::with_capacity(cubes.len());
+
+    for (y, line) in cubes.lines().enumerate() {
+        for (x, cube) in line.trim().chars().enumerate() {
+            if cube == '#' {
+                let mut coordinate = [0; D];
+                coordinate[0] = x as i32;
+                coordinate[1] = y as i32;
+                grid.insert(coordinate);
+            }
+        }
+    }
+
+    grid
+}
+
+// The cube itself and every cube touching it.
+pub fn surrounding<const D: usize>(cube: Coordinate<D>) -> BoundingBox<D> {
+    BoundingBox::new(cube, cube).expand(1)
+}
+
+fn neighbours<const D: usize>(position: Coordinate<D>, grid: &HashSet<Coordinate<D>>) -> usize {
+    surrounding(position)
+        .into_iter()
+        .filter(|cell| *cell != position && grid.contains(cell))
+        .count()
+}
+
+pub fn offset<const D: usize>(cube: Coordinate<D>, by: Coordinate<D>) -> Coordinate<D> {
+    let mut moved = cube;
+    for (c, b) in moved.iter_mut().zip(by.iter()) {
+        *c += b;
+    }
+    moved
+}
+
+pub fn next_generation<const D: usize>(
+    previous: &HashSet<Coordinate<D>>,
+) -> HashSet<Coordinate<D>> {
+    next_generation_with(previous, &Rule::conway())
+}
+
+// Every active cube adds one to the count of each cube in its neighbourhood, so only cubes
+// near an active one are ever looked at. Active cubes are counted too, with nothing
+// added, in case the rule lets them survive alone.
+pub fn next_generation_with<const D: usize>(
+    previous: &HashSet<Coordinate<D>>,
+    rule: &Rule,
+) -> HashSet<Coordinate<D>> {
+    let offsets = rule.neighbourhood().offsets::<D>();
+    let mut counts: HashMap<Coordinate<D>, usize> = HashMap::with_capacity(previous.len() * 4);
+
+    for &cube in previous {
+        counts.entry(cube).or_insert(0);
+        for &by in &offsets {
+            *counts.entry(offset(cube, by)).or_insert(0) += 1;
+        }
+    }
+
+    counts
+        .into_iter()
+        .filter(|(cube, count)| rule.is_active(previous.contains(cube), *count))
+        .map(|(cube, _)| cube)
+        .collect()
+}
+
+// The original approach, kept for comparison: counts the neighbours of every cube in the
+// bounding box, with a lookup for each one.
+pub fn next_generation_by_scanning<const D: usize>(
+    previous: &HashSet<Coordinate<D>>,
+) -> HashSet<Coordinate<D>> {
+    let mut next = previous.clone();
+
+    next.retain(|&coord| matches!(neighbours(coord, previous), 2 | 3));
+
+    if let Some(bounds) = BoundingBox::around(previous) {
+        for cell in bounds.expand(1) {
+            if neighbours(cell, previous) == 3 {
+                next.insert(cell);
+            }
+        }
+    }
+
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Neighbourhood;
+
+    const EXAMPLE: &str = ".#.
+        ..#
+        ###";
+
+    fn run<const D: usize>(cycles: usize) -> usize {
+        let mut grid = read_cubes::<D>(EXAMPLE);
+        for _ in 0..cycles {
+            grid = next_generation(&grid);
+        }
+        grid.len()
+    }
+
+    #[test]
+    fn seeds_the_first_two_axes() {
+        let grid = read_cubes::<4>(EXAMPLE);
+        assert_eq!(5, grid.len());
+        assert!(grid.contains(&[1, 0, 0, 0]));
+        assert!(grid.contains(&[2, 2, 0, 0]));
+    }
+
+    #[test]
+    fn example_in_three_dimensions() {
+        assert_eq!(11, run::<3>(1));
+        assert_eq!(112, run::<3>(6));
+    }
+
+    #[test]
+    fn scanning_agrees_with_counting() {
+        let mut grid = read_cubes::<4>(EXAMPLE);
+        for _ in 0..3 {
+            let next = next_generation(&grid);
+            assert_eq!(next, next_generation_by_scanning(&grid));
+            grid = next;
+        }
+
+        // Far from the origin, and empty.
+        let far: HashSet<[i32; 3]> = read_cubes::<3>(EXAMPLE)
+            .iter()
+            .map(|c| c.map(|x| x + 100))
+            .collect();
+        assert_eq!(next_generation(&far), next_generation_by_scanning(&far));
+        assert!(next_generation_by_scanning::<3>(&HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn example_in_four_dimensions() {
+        assert_eq!(29, run::<4>(1));
+        assert_eq!(848, run::<4>(6));
+    }
+
+    #[test]
+    fn blinker_in_two_dimensions() {
+        let horizontal = read_cubes::<2>("###");
+        let vertical = next_generation(&horizontal);
+        assert_eq!(
+            [[1, -1], [1, 0], [1, 1]].iter().copied().collect::<HashSet<_>>(),
+            vertical
+        );
+        assert_eq!(horizontal, next_generation(&vertical));
+    }
+
+    #[test]
+    fn other_rules() {
+        // Every cube survives with no neighbours, and a cube is born next to exactly one.
+        let grow = Rule::new(&[1], &[0, 1, 2, 3, 4, 5, 6], Neighbourhood::VonNeumann(1));
+        let mut grid = read_cubes::<3>("#");
+        for size in &[7, 13, 43] {
+            grid = next_generation_with(&grid, &grow);
+            assert_eq!(*size, grid.len());
+        }
+
+        // Lone cubes survive, but nothing is ever born two steps away.
+        let far = Rule::new(&[3], &[0], Neighbourhood::Moore(2));
+        let grid = read_cubes::<2>("#...#");
+        assert_eq!(grid, next_generation_with(&grid, &far));
+    }
+}
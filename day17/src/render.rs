@@ -0,0 +1,159 @@
+use crate::bounding_box::BoundingBox;
+use crate::n_d::Coordinate;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+// Axes past w are w2, w3 and so on.
+fn axis_name(axis: usize) -> String {
+    match axis {
+        0 => "x".to_string(),
+        1 => "y".to_string(),
+        2 => "z".to_string(),
+        3 => "w".to_string(),
+        _ => format!("w{}", axis - 2),
+    }
+}
+
+// Every x-y slice of the grid within its bounding box, headed by its other coordinates and
+// with z changing fastest, as in the puzzle's worked example:
+//
+// z=-1, w=0
+// #..
+// ..#
+// .#.
+pub fn render_slices<const D: usize>(grid: &HashSet<Coordinate<D>>) -> String {
+    let bounds = match BoundingBox::around(grid) {
+        Some(bounds) => bounds,
+        None => return String::new(),
+    };
+
+    // Find the slices with x and y pinned, then fill each one in. Boxes vary their last
+    // axis fastest, so the slices are put back in order of their last axis first.
+    let mut slices = bounds;
+    slices.max[0] = slices.min[0];
+    slices.max[1] = slices.min[1];
+    let mut corners: Vec<Coordinate<D>> = slices.into_iter().collect();
+    corners.sort_by_key(|corner| {
+        let mut key = *corner;
+        key.reverse();
+        key
+    });
+
+    let mut text = String::new();
+    for corner in corners {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+
+        let header: Vec<String> = (2..D)
+            .map(|axis| format!("{}={}", axis_name(axis), corner[axis]))
+            .collect();
+        if !header.is_empty() {
+            writeln!(text, "{}", header.join(", ")).unwrap();
+        }
+
+        let mut cube = corner;
+        for y in bounds.min[1]..=bounds.max[1] {
+            cube[1] = y;
+            for x in bounds.min[0]..=bounds.max[0] {
+                cube[0] = x;
+                text.push(if grid.contains(&cube) { '#' } else { '.' });
+            }
+            text.push('\n');
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::n_d::{next_generation, read_cubes};
+
+    #[test]
+    fn renders_the_example_after_one_cycle() {
+        let grid = next_generation(&read_cubes::<3>(".#.\n..#\n###"));
+        let expected = "z=-1
+#..
+..#
+.#.
+
+z=0
+#.#
+.##
+.#.
+
+z=1
+#..
+..#
+.#.
+";
+        assert_eq!(expected, render_slices(&grid));
+    }
+
+    #[test]
+    fn renders_the_four_dimensional_example_after_one_cycle() {
+        let grid = next_generation(&read_cubes::<4>(".#.\n..#\n###"));
+        let expected = "z=-1, w=-1
+#..
+..#
+.#.
+
+z=0, w=-1
+#..
+..#
+.#.
+
+z=1, w=-1
+#..
+..#
+.#.
+
+z=-1, w=0
+#..
+..#
+.#.
+
+z=0, w=0
+#.#
+.##
+.#.
+
+z=1, w=0
+#..
+..#
+.#.
+
+z=-1, w=1
+#..
+..#
+.#.
+
+z=0, w=1
+#..
+..#
+.#.
+
+z=1, w=1
+#..
+..#
+.#.
+";
+        assert_eq!(expected, render_slices(&grid));
+    }
+
+    #[test]
+    fn renders_four_dimensions_and_nothing() {
+        let grid = read_cubes::<4>("#.\n.#");
+        assert_eq!("z=0, w=0\n#.\n.#\n", render_slices(&grid));
+        assert_eq!("#.\n.#\n", render_slices(&read_cubes::<2>("#.\n.#")));
+        assert_eq!("", render_slices::<3>(&HashSet::new()));
+    }
+
+    #[test]
+    fn names_extra_axes_after_w() {
+        let grid = read_cubes::<6>("#");
+        assert_eq!("z=0, w=0, w2=0, w3=0\n#\n", render_slices(&grid));
+    }
+}
@@ -0,0 +1,109 @@
+use crate::bounding_box::BoundingBox;
+use crate::n_d::Coordinate;
+use std::collections::BTreeSet;
+
+// Which cubes count as neighbours of a cube, by their offset from it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Neighbourhood {
+    // Every cube within `radius` along every axis: a (2r+1)^D box less the centre.
+    Moore(i32),
+    // Every cube within `radius` steps along the axes, so no diagonals at radius 1.
+    VonNeumann(i32),
+}
+
+impl Neighbourhood {
+    pub fn offsets<const D: usize>(&self) -> Vec<Coordinate<D>> {
+        let radius = match *self {
+            Neighbourhood::Moore(r) | Neighbourhood::VonNeumann(r) => r,
+        };
+        assert!(radius >= 0, "The radius of a neighbourhood can't be negative");
+
+        BoundingBox::new([0; D], [0; D])
+            .expand(radius)
+            .into_iter()
+            .filter(|offset| offset.iter().any(|&o| o != 0))
+            .filter(|offset| match self {
+                Neighbourhood::Moore(_) => true,
+                Neighbourhood::VonNeumann(r) => offset.iter().map(|o| o.abs()).sum::<i32>() <= *r,
+            })
+            .collect()
+    }
+}
+
+// A cellular automaton: an inactive cube becomes active when its number of active
+// neighbours is in `birth`, and an active cube stays active when it's in `survival`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Rule {
+    birth: BTreeSet<usize>,
+    survival: BTreeSet<usize>,
+    neighbourhood: Neighbourhood,
+}
+
+impl Rule {
+    pub fn new(birth: &[usize], survival: &[usize], neighbourhood: Neighbourhood) -> Self {
+        // Only cubes next to an active one are ever looked at, and an infinite grid
+        // can't be stored anyway.
+        assert!(
+            !birth.contains(&0),
+            "Cubes can't become active with no active neighbours"
+        );
+        Rule {
+            birth: birth.iter().copied().collect(),
+            survival: survival.iter().copied().collect(),
+            neighbourhood,
+        }
+    }
+
+    // The puzzle's rule, which is Conway's Game of Life in two dimensions.
+    pub fn conway() -> Self {
+        Rule::new(&[3], &[2, 3], Neighbourhood::Moore(1))
+    }
+
+    pub fn neighbourhood(&self) -> Neighbourhood {
+        self.neighbourhood
+    }
+
+    pub fn is_active(&self, active: bool, neighbours: usize) -> bool {
+        if active {
+            self.survival.contains(&neighbours)
+        } else {
+            self.birth.contains(&neighbours)
+        }
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule::conway()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbourhood_sizes() {
+        assert_eq!(8, Neighbourhood::Moore(1).offsets::<2>().len());
+        assert_eq!(26, Neighbourhood::Moore(1).offsets::<3>().len());
+        assert_eq!(24, Neighbourhood::Moore(2).offsets::<2>().len());
+        assert_eq!(6, Neighbourhood::VonNeumann(1).offsets::<3>().len());
+        assert_eq!(12, Neighbourhood::VonNeumann(2).offsets::<2>().len());
+        assert_eq!(0, Neighbourhood::Moore(0).offsets::<3>().len());
+    }
+
+    #[test]
+    fn conway_rule() {
+        let rule = Rule::conway();
+        assert!(rule.is_active(false, 3));
+        assert!(!rule.is_active(false, 2));
+        assert!(rule.is_active(true, 2));
+        assert!(!rule.is_active(true, 4));
+    }
+
+    #[test]
+    #[should_panic(expected = "no active neighbours")]
+    fn rejects_birth_from_nothing() {
+        Rule::new(&[0, 1], &[], Neighbourhood::VonNeumann(1));
+    }
+}
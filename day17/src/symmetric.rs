@@ -0,0 +1,162 @@
+use crate::bounding_box::BoundingBox;
+use crate::n_d::{offset, Coordinate};
+use crate::rules::Rule;
+use std::collections::{HashMap, HashSet};
+
+// Conway Cubes seeded in the slice where every axis after x and y is zero. Nothing in the
+// rules tells positive and negative apart, so the grid stays symmetric under flipping the
+// sign of any of those axes, and only the half-space where they are all non-negative
+// needs to be simulated. Each cube stored stands for `multiplicity` cubes in the full grid.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Mirrored<const D: usize> {
+    cubes: HashSet<Coordinate<D>>,
+}
+
+fn fold<const D: usize>(cube: Coordinate<D>) -> Coordinate<D> {
+    let mut folded = cube;
+    for c in folded.iter_mut().skip(2) {
+        *c = c.abs();
+    }
+    folded
+}
+
+fn multiplicity<const D: usize>(cube: Coordinate<D>) -> usize {
+    1 << cube.iter().skip(2).filter(|&&c| c != 0).count()
+}
+
+impl<const D: usize> Mirrored<D> {
+    pub fn new(seed: &HashSet<Coordinate<D>>) -> Self {
+        assert!(
+            seed.iter().all(|cube| cube.iter().skip(2).all(|&c| c == 0)),
+            "The seed must lie in the slice through the origin"
+        );
+        Mirrored {
+            cubes: seed.clone(),
+        }
+    }
+
+    pub fn next_generation(&self) -> Self {
+        self.next_generation_with(&Rule::conway())
+    }
+
+    // A neighbour `n` of a stored cube `a` folds to `f`. Counting every such pair with
+    // weight `multiplicity(a)` counts each pair of a cube mirroring `a` next to a cube
+    // mirroring `f`, so dividing by `multiplicity(f)` leaves the number of active
+    // neighbours of `f` itself. Both neighbourhoods are unchanged by flipping an axis, so
+    // this holds for any rule.
+    pub fn next_generation_with(&self, rule: &Rule) -> Self {
+        let offsets = rule.neighbourhood().offsets::<D>();
+        let mut weighted: HashMap<Coordinate<D>, usize> = HashMap::new();
+
+        for &cube in &self.cubes {
+            let weight = multiplicity(cube);
+            weighted.entry(cube).or_insert(0);
+            for &by in &offsets {
+                *weighted.entry(fold(offset(cube, by))).or_insert(0) += weight;
+            }
+        }
+
+        let cubes = weighted
+            .into_iter()
+            .filter(|&(cube, weight)| {
+                let count = weight / multiplicity(cube);
+                rule.is_active(self.cubes.contains(&cube), count)
+            })
+            .map(|(cube, _)| cube)
+            .collect();
+
+        Mirrored { cubes }
+    }
+
+    // The number of active cubes in the full grid.
+    pub fn len(&self) -> usize {
+        self.cubes.iter().map(|&cube| multiplicity(cube)).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cubes.is_empty()
+    }
+
+    // The cubes actually stored, one per set of mirror images.
+    pub fn stored(&self) -> usize {
+        self.cubes.len()
+    }
+
+    // Every active cube in the full grid.
+    pub fn unfold(&self) -> HashSet<Coordinate<D>> {
+        let mut full = HashSet::with_capacity(self.len());
+        for &cube in &self.cubes {
+            // -1 on an axis of `flips` means that coordinate is negated.
+            let mut min_flips = [0; D];
+            for axis in 2..D {
+                if cube[axis] != 0 {
+                    min_flips[axis] = -1;
+                }
+            }
+            for flips in BoundingBox::new(min_flips, [0; D]) {
+                let mut image = cube;
+                for axis in 2..D {
+                    if flips[axis] < 0 {
+                        image[axis] = -cube[axis];
+                    }
+                }
+                full.insert(image);
+            }
+        }
+        full
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::n_d::{next_generation, next_generation_with, read_cubes};
+    use crate::rules::Neighbourhood;
+
+    const EXAMPLE: &str = ".#.
+        ..#
+        ###";
+
+    #[test]
+    fn matches_full_simulation() {
+        let mut full = read_cubes::<4>(EXAMPLE);
+        let mut mirrored = Mirrored::new(&full);
+
+        for _ in 0..4 {
+            full = next_generation(&full);
+            mirrored = mirrored.next_generation();
+            assert_eq!(full.len(), mirrored.len());
+            assert_eq!(full, mirrored.unfold());
+        }
+        assert!(mirrored.stored() < mirrored.len());
+    }
+
+    #[test]
+    fn matches_full_simulation_with_other_rules() {
+        let rules = [
+            Rule::new(&[1, 3], &[1, 2], Neighbourhood::VonNeumann(2)),
+            Rule::new(&[4, 5, 6], &[3, 4, 5, 6, 7], Neighbourhood::Moore(2)),
+        ];
+        for rule in &rules {
+            let mut full = read_cubes::<3>(EXAMPLE);
+            let mut mirrored = Mirrored::new(&full);
+            for _ in 0..3 {
+                full = next_generation_with(&full, rule);
+                mirrored = mirrored.next_generation_with(rule);
+                assert_eq!(full, mirrored.unfold());
+            }
+        }
+    }
+
+    #[test]
+    fn example_counts() {
+        let mut three = Mirrored::new(&read_cubes::<3>(EXAMPLE));
+        let mut four = Mirrored::new(&read_cubes::<4>(EXAMPLE));
+        for _ in 0..6 {
+            three = three.next_generation();
+            four = four.next_generation();
+        }
+        assert_eq!(112, three.len());
+        assert_eq!(848, four.len());
+    }
+}
@@ -0,0 +1,425 @@
+use crate::matching::{MatchResult, Matcher};
+use crate::parse_tree::{Diagnostics, Parse};
+use crate::parsing::Rule;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+pub fn reachable(start: usize, rules: &HashMap<usize, Rule>) -> BTreeSet<usize> {
+    let mut seen = BTreeSet::new();
+    let mut pending = vec![start];
+
+    while let Some(index) = pending.pop() {
+        if seen.insert(index) {
+            pending.extend(rules[&index].references());
+        }
+    }
+
+    seen
+}
+
+pub fn unreachable(start: usize, rules: &HashMap<usize, Rule>) -> BTreeSet<usize> {
+    let reachable = reachable(start, rules);
+    rules
+        .keys()
+        .filter(|index| !reachable.contains(index))
+        .copied()
+        .collect()
+}
+
+// Groups of rules that refer to each other, directly or indirectly, found with Tarjan's
+// algorithm. Each group is sorted, as is the list of groups.
+pub fn cycles(rules: &HashMap<usize, Rule>) -> Vec<Vec<usize>> {
+    #[derive(Default)]
+    struct Tarjan {
+        next: usize,
+        order: HashMap<usize, usize>,
+        low_link: HashMap<usize, usize>,
+        stack: Vec<usize>,
+        on_stack: HashSet<usize>,
+        cycles: Vec<Vec<usize>>,
+    }
+
+    impl Tarjan {
+        fn visit(&mut self, index: usize, rules: &HashMap<usize, Rule>) {
+            self.order.insert(index, self.next);
+            self.low_link.insert(index, self.next);
+            self.next += 1;
+            self.stack.push(index);
+            self.on_stack.insert(index);
+
+            let references = rules[&index].references();
+            for &n in &references {
+                if !self.order.contains_key(&n) {
+                    self.visit(n, rules);
+                    let low = self.low_link[&index].min(self.low_link[&n]);
+                    self.low_link.insert(index, low);
+                } else if self.on_stack.contains(&n) {
+                    let low = self.low_link[&index].min(self.order[&n]);
+                    self.low_link.insert(index, low);
+                }
+            }
+
+            if self.low_link[&index] == self.order[&index] {
+                let mut component = Vec::new();
+                while let Some(n) = self.stack.pop() {
+                    self.on_stack.remove(&n);
+                    component.push(n);
+                    if n == index {
+                        break;
+                    }
+                }
+                if component.len() > 1 || references.contains(&index) {
+                    component.sort_unstable();
+                    self.cycles.push(component);
+                }
+            }
+        }
+    }
+
+    let mut indices: Vec<usize> = rules.keys().copied().collect();
+    indices.sort_unstable();
+
+    let mut tarjan = Tarjan::default();
+    for index in indices {
+        if !tarjan.order.contains_key(&index) {
+            tarjan.visit(index, rules);
+        }
+    }
+
+    tarjan.cycles.sort();
+    tarjan.cycles
+}
+
+// Rules that can derive the empty string. The rule file syntax can't express one, but rule
+// maps built by hand can.
+pub fn nullable(rules: &HashMap<usize, Rule>) -> BTreeSet<usize> {
+    let mut nullable = BTreeSet::new();
+
+    loop {
+        let before = nullable.len();
+        for (&index, rule) in rules {
+            let empty = match rule {
+                Rule::Literal(text) => text.is_empty(),
+                _ => rule
+                    .alternatives()
+                    .iter()
+                    .any(|numbers| numbers.iter().all(|n| nullable.contains(n))),
+            };
+            if empty {
+                nullable.insert(index);
+            }
+        }
+        if nullable.len() == before {
+            return nullable;
+        }
+    }
+}
+
+// Lengths in characters of the messages a rule can derive. `max` is `None` if the rule can
+// reach a cycle, in which case there may be no limit.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LengthBounds {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl LengthBounds {
+    pub fn contains(&self, length: usize) -> bool {
+        length >= self.min && self.max.map_or(true, |max| length <= max)
+    }
+}
+
+// Bounds for every rule that can derive at least one message.
+pub fn length_bounds(rules: &HashMap<usize, Rule>) -> HashMap<usize, LengthBounds> {
+    fn max_length(
+        index: usize,
+        rules: &HashMap<usize, Rule>,
+        recursive: &HashSet<usize>,
+        memo: &mut HashMap<usize, Option<usize>>,
+    ) -> Option<usize> {
+        if recursive.contains(&index) {
+            return None;
+        }
+        if let Some(&max) = memo.get(&index) {
+            return max;
+        }
+
+        let rule = &rules[&index];
+        let max = match rule {
+            Rule::Literal(text) => Some(text.chars().count()),
+            _ => rule
+                .alternatives()
+                .into_iter()
+                .map(|numbers| {
+                    numbers
+                        .iter()
+                        .map(|&n| max_length(n, rules, recursive, memo))
+                        .sum::<Option<usize>>()
+                })
+                // Unbounded if any alternative is.
+                .collect::<Option<Vec<usize>>>()
+                .and_then(|maxima| maxima.into_iter().max()),
+        };
+
+        memo.insert(index, max);
+        max
+    }
+
+    let mut min_lengths: HashMap<usize, usize> = HashMap::new();
+    loop {
+        let mut changed = false;
+        for (&index, rule) in rules {
+            let min = match rule {
+                Rule::Literal(text) => Some(text.chars().count()),
+                _ => rule
+                    .alternatives()
+                    .into_iter()
+                    .filter_map(|numbers| numbers.iter().map(|n| min_lengths.get(n)).sum())
+                    .min(),
+            };
+            if let Some(min) = min {
+                if min_lengths.get(&index).map_or(true, |&m| min < m) {
+                    min_lengths.insert(index, min);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let in_cycles: Vec<usize> = cycles(rules).into_iter().flatten().collect();
+    let recursive: HashSet<usize> = rules
+        .keys()
+        .filter(|&&index| {
+            let reachable = reachable(index, rules);
+            in_cycles.iter().any(|n| reachable.contains(n))
+        })
+        .copied()
+        .collect();
+
+    let mut memo = HashMap::new();
+    min_lengths
+        .into_iter()
+        .map(|(index, min)| {
+            let max = max_length(index, rules, &recursive, &mut memo);
+            (index, LengthBounds { min, max })
+        })
+        .collect()
+}
+
+// The characters that messages derived from each rule can start with.
+pub fn first_characters(rules: &HashMap<usize, Rule>) -> HashMap<usize, BTreeSet<char>> {
+    let nullable = nullable(rules);
+    let mut first: HashMap<usize, BTreeSet<char>> =
+        rules.keys().map(|&index| (index, BTreeSet::new())).collect();
+
+    loop {
+        let mut changed = false;
+        for (&index, rule) in rules {
+            let mut characters = BTreeSet::new();
+            match rule {
+                Rule::Literal(text) => characters.extend(text.chars().next()),
+                _ => {
+                    for numbers in rule.alternatives() {
+                        for n in numbers {
+                            characters.extend(&first[n]);
+                            if !nullable.contains(n) {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+            if characters.len() > first[&index].len() {
+                first.insert(index, characters);
+                changed = true;
+            }
+        }
+        if !changed {
+            return first;
+        }
+    }
+}
+
+// Wraps the matcher for one rule so that input which is too short or starts with the
+// wrong character is rejected without running it, and `exactly_matches` also rejects
+// messages that are too long. `parses` is not pruned.
+pub struct Pruned<M> {
+    matcher: M,
+    bounds: Option<LengthBounds>,
+    first: BTreeSet<char>,
+    nullable: bool,
+}
+
+pub fn prune<M>(matcher: M, index: usize, rules: &HashMap<usize, Rule>) -> Pruned<M> {
+    Pruned {
+        matcher,
+        bounds: length_bounds(rules).get(&index).copied(),
+        first: first_characters(rules).remove(&index).unwrap_or_default(),
+        nullable: nullable(rules).contains(&index),
+    }
+}
+
+impl<M> Pruned<M> {
+    fn could_start(&self, input: &str) -> bool {
+        let long_enough = |min: usize| min == 0 || input.chars().nth(min - 1).is_some();
+        let first_matches = match input.chars().next() {
+            Some(c) => self.first.contains(&c),
+            None => false,
+        };
+        let bounds_allow = self.bounds.map_or(false, |bounds| long_enough(bounds.min));
+        bounds_allow && (self.nullable || first_matches)
+    }
+}
+
+impl<'a, M> Matcher<&'a str> for Pruned<M>
+where
+    M: Matcher<&'a str>,
+{
+    fn matches(&self, input: &'a str) -> MatchResult<&'a str> {
+        if self.could_start(input) {
+            self.matcher.matches(input)
+        } else {
+            Err(input)
+        }
+    }
+
+    fn remainders(&self, input: &'a str) -> Vec<&'a str> {
+        if self.could_start(input) {
+            self.matcher.remainders(input)
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn exactly_matches(&self, input: &'a str) -> bool {
+        let length = input.chars().count();
+        self.bounds.map_or(false, |bounds| bounds.contains(length))
+            && self.could_start(input)
+            && self.matcher.exactly_matches(input)
+    }
+
+    // Not pruned: this always runs the wrapped matcher, so that failures still reach
+    // `diagnostics` with the position where matching stopped.
+    fn parses(&self, input: &'a str, diagnostics: &mut Diagnostics) -> Vec<Parse<&'a str>> {
+        self.matcher.parses(input, diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::{create_matcher, extend_rules, parse_rules};
+
+    const EXAMPLE: &str = "0: 4 1 5
+        1: 2 3 | 3 2
+        2: 4 4 | 5 5
+        3: 4 5 | 5 4
+        4: \"a\"
+        5: \"b\"";
+
+    fn looping_rules() -> HashMap<usize, Rule> {
+        let mut rules = parse_rules(include_str!("rules.txt")).unwrap();
+        extend_rules(&mut rules, "8: 42 | 42 8\n11: 42 31 | 42 11 31").unwrap();
+        rules
+    }
+
+    #[test]
+    fn finds_unreachable_rules() {
+        let mut rules = parse_rules(EXAMPLE).unwrap();
+        assert!(unreachable(0, &rules).is_empty());
+
+        extend_rules(&mut rules, "6: 4 5\n7: 6 1").unwrap();
+        assert_eq!(vec![6, 7], unreachable(0, &rules).into_iter().collect::<Vec<_>>());
+        assert_eq!(vec![0, 6, 7], unreachable(1, &rules).into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn finds_cycles() {
+        assert!(cycles(&parse_rules(EXAMPLE).unwrap()).is_empty());
+        assert_eq!(vec![vec![8], vec![11]], cycles(&looping_rules()));
+
+        let rules = parse_rules("0: 1\n1: 2 | 3\n2: 1 3\n3: \"a\"").unwrap();
+        assert_eq!(vec![vec![1, 2]], cycles(&rules));
+    }
+
+    #[test]
+    fn nullable_rules_and_first_characters() {
+        let mut rules = HashMap::new();
+        rules.insert(0, Rule::Either(vec![vec![1], vec![2]]));
+        rules.insert(1, Rule::Sequence(Vec::new()));
+        rules.insert(2, Rule::Literal("ab".to_string()));
+        rules.insert(3, Rule::Sequence(vec![1, 0, 2]));
+        rules.insert(4, Rule::Literal("c".to_string()));
+        rules.insert(5, Rule::Sequence(vec![4, 2]));
+
+        assert_eq!(vec![0, 1], nullable(&rules).into_iter().collect::<Vec<_>>());
+
+        let first = first_characters(&rules);
+        assert!(first[&1].is_empty());
+        assert_eq!(vec!['a'], first[&3].iter().copied().collect::<Vec<_>>());
+        assert_eq!(vec!['c'], first[&5].iter().copied().collect::<Vec<_>>());
+
+        let example = first_characters(&parse_rules(EXAMPLE).unwrap());
+        assert_eq!(vec!['a'], example[&0].iter().copied().collect::<Vec<_>>());
+        assert_eq!(vec!['a', 'b'], example[&1].iter().copied().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn computes_length_bounds() {
+        let bounds = length_bounds(&parse_rules(EXAMPLE).unwrap());
+        assert_eq!(LengthBounds { min: 6, max: Some(6) }, bounds[&0]);
+        assert_eq!(LengthBounds { min: 4, max: Some(4) }, bounds[&1]);
+
+        let bounds = length_bounds(&looping_rules());
+        assert_eq!(LengthBounds { min: 24, max: None }, bounds[&0]);
+        assert_eq!(LengthBounds { min: 8, max: Some(8) }, bounds[&42]);
+
+        // Only rules 0 and 2 derive anything.
+        let rules = parse_rules("0: 1 | 2\n1: 1 2\n2: \"ab\"").unwrap();
+        let bounds = length_bounds(&rules);
+        assert_eq!(LengthBounds { min: 2, max: None }, bounds[&0]);
+        assert!(!bounds.contains_key(&1));
+    }
+
+    #[test]
+    fn unbounded_alternative_wins() {
+        let rules = parse_rules("0: 8 | 4\n8: 4 | 4 8\n4: \"a\"").unwrap();
+        let bounds = length_bounds(&rules);
+        assert_eq!(LengthBounds { min: 1, max: None }, bounds[&0]);
+
+        let pruned = prune(create_matcher(0, &rules), 0, &rules);
+        assert!(pruned.exactly_matches("a"));
+        assert!(pruned.exactly_matches("aaaaa"));
+    }
+
+    #[test]
+    fn pruning_skips_the_matcher() {
+        let rules = parse_rules(EXAMPLE).unwrap();
+        let refuse = |_: &str| -> MatchResult<&str> { panic!("should have been pruned") };
+        let pruned = prune(refuse, 0, &rules);
+
+        assert!(!pruned.exactly_matches("ababb"));
+        assert!(!pruned.exactly_matches("ababbba"));
+        assert!(!pruned.exactly_matches("bbabbb"));
+        assert_eq!(Err("abab"), pruned.matches("abab"));
+        assert!(pruned.remainders("bababab").is_empty());
+    }
+
+    #[test]
+    fn pruning_keeps_results() {
+        let rules = looping_rules();
+        let matcher = create_matcher(0, &rules);
+        let pruned = prune(create_matcher(0, &rules), 0, &rules);
+
+        for message in include_str!("messages.txt").lines() {
+            let expected = matcher.exactly_matches(message);
+            assert_eq!(expected, pruned.exactly_matches(message), "{}", message);
+        }
+
+        let rules = parse_rules(EXAMPLE).unwrap();
+        let pruned = prune(create_matcher(0, &rules), 0, &rules);
+        assert_eq!(Ok("b"), pruned.matches("aaaabbb"));
+    }
+}
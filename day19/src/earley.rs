@@ -0,0 +1,398 @@
+use crate::matching::{MatchResult, Matcher};
+use crate::parsing::Rule;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+
+// An Earley recogniser, which accepts exactly the strings derivable from a rule for any
+// context-free rule set, including left-recursive and ambiguous ones that the combinators
+// in `matching` can't handle.
+pub struct Earley {
+    start: usize,
+    productions: HashMap<usize, Vec<Vec<Symbol>>>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+enum Symbol {
+    Rule(usize),
+    Char(char),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+struct Item {
+    rule: usize,
+    alternative: usize,
+    dot: usize,
+    origin: usize,
+}
+
+// One node of the derivation found for the input: which alternative of which rule was used
+// and the byte range of the input it covers.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Derivation {
+    pub rule: usize,
+    pub alternative: usize,
+    pub span: Range<usize>,
+    pub children: Vec<Derivation>,
+}
+
+impl Derivation {
+    fn write_indented(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        writeln!(
+            f,
+            "{:indent$}{} (alternative {}) {}..{}",
+            "",
+            self.rule,
+            self.alternative,
+            self.span.start,
+            self.span.end,
+            indent = depth * 2
+        )?;
+        for child in &self.children {
+            child.write_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Derivation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+fn alternatives(rule: &Rule) -> Vec<Vec<Symbol>> {
+    let sequence = |numbers: &[usize]| numbers.iter().map(|&n| Symbol::Rule(n)).collect();
+    match rule {
+        Rule::Literal(text) => vec![text.chars().map(Symbol::Char).collect()],
+        Rule::Sequence(numbers) => vec![sequence(numbers)],
+        Rule::Either(alternatives) => alternatives.iter().map(|a| sequence(a)).collect(),
+    }
+}
+
+// The filled-in chart for one input. `chart[i]` holds the items alive after reading `i` characters.
+struct Chart<'e> {
+    chars: Vec<(usize, char)>,
+    input_len: usize,
+    productions: &'e HashMap<usize, Vec<Vec<Symbol>>>,
+    completed: HashSet<(usize, usize, usize)>,
+    ends: Vec<usize>,
+}
+
+impl Earley {
+    pub fn new(start: usize, rules: &HashMap<usize, Rule>) -> Self {
+        let productions = rules
+            .iter()
+            .map(|(&index, rule)| (index, alternatives(rule)))
+            .collect();
+        Earley { start, productions }
+    }
+
+    fn chart(&self, input: &str) -> Chart<'_> {
+        let productions = &self.productions;
+        let chars: Vec<(usize, char)> = input.char_indices().collect();
+        let n = chars.len();
+
+        let mut items: Vec<Vec<Item>> = vec![Vec::new(); n + 1];
+        let mut seen: Vec<HashSet<Item>> = vec![HashSet::new(); n + 1];
+
+        let add = |items: &mut Vec<Vec<Item>>, seen: &mut Vec<HashSet<Item>>, at: usize, item: Item| {
+            if seen[at].insert(item) {
+                items[at].push(item);
+            }
+        };
+
+        let predict = |items: &mut Vec<Vec<Item>>, seen: &mut Vec<HashSet<Item>>, rule: usize, at: usize| {
+            let alternatives = productions.get(&rule).expect("Looking for non-existent rule");
+            for alternative in 0..alternatives.len() {
+                let item = Item {
+                    rule,
+                    alternative,
+                    dot: 0,
+                    origin: at,
+                };
+                add(items, seen, at, item);
+            }
+        };
+
+        predict(&mut items, &mut seen, self.start, 0);
+
+        for i in 0..=n {
+            let mut j = 0;
+            while j < items[i].len() {
+                let item = items[i][j];
+                let symbols = &productions[&item.rule][item.alternative];
+
+                match symbols.get(item.dot) {
+                    Some(Symbol::Rule(r)) => predict(&mut items, &mut seen, *r, i),
+                    Some(Symbol::Char(c)) => {
+                        if i < n && chars[i].1 == *c {
+                            add(&mut items, &mut seen, i + 1, Item { dot: item.dot + 1, ..item });
+                        }
+                    }
+                    None => {
+                        // Rules never derive the empty string, so `origin < i` here.
+                        let waiting: Vec<Item> = items[item.origin]
+                            .iter()
+                            .filter(|w| {
+                                productions[&w.rule][w.alternative].get(w.dot)
+                                    == Some(&Symbol::Rule(item.rule))
+                            })
+                            .copied()
+                            .collect();
+                        for w in waiting {
+                            add(&mut items, &mut seen, i, Item { dot: w.dot + 1, ..w });
+                        }
+                    }
+                }
+
+                j += 1;
+            }
+        }
+
+        let mut completed = HashSet::new();
+        for (end, column) in items.iter().enumerate() {
+            for item in column {
+                if item.dot == productions[&item.rule][item.alternative].len() {
+                    completed.insert((item.rule, item.origin, end));
+                }
+            }
+        }
+
+        let ends = (0..=n)
+            .filter(|&end| completed.contains(&(self.start, 0, end)))
+            .collect();
+
+        Chart {
+            chars,
+            input_len: input.len(),
+            productions,
+            completed,
+            ends,
+        }
+    }
+
+    pub fn recognises(&self, input: &str) -> bool {
+        let chart = self.chart(input);
+        chart.ends.last() == Some(&chart.chars.len())
+    }
+
+    // The derivation of the whole input from the start rule. Ambiguous input gets the
+    // derivation that prefers earlier alternatives.
+    pub fn parse(&self, input: &str) -> Option<Derivation> {
+        let chart = self.chart(input);
+        let mut builder = Builder {
+            chart: &chart,
+            visiting: HashMap::new(),
+            memo: HashMap::new(),
+            lowest_cut: usize::MAX,
+        };
+        builder.build(self.start, 0, chart.chars.len())
+    }
+}
+
+impl Chart<'_> {
+    fn offset(&self, position: usize) -> usize {
+        self.chars.get(position).map_or(self.input_len, |&(offset, _)| offset)
+    }
+}
+
+// Reads a derivation out of a chart, remembering the one found for each rule and span.
+struct Builder<'c, 'e> {
+    chart: &'c Chart<'e>,
+    // The rule and span being built at each depth of the current derivation.
+    visiting: HashMap<(usize, usize, usize), usize>,
+    memo: HashMap<(usize, usize, usize), Option<Derivation>>,
+    // The shallowest depth whose rule and span had to be skipped to break a cycle.
+    lowest_cut: usize,
+}
+
+impl Builder<'_, '_> {
+    fn build(&mut self, rule: usize, start: usize, end: usize) -> Option<Derivation> {
+        let key = (rule, start, end);
+        if !self.chart.completed.contains(&key) {
+            return None;
+        }
+        if let Some(derivation) = self.memo.get(&key) {
+            return derivation.clone();
+        }
+        // A rule can only derive itself over the same span through a cycle of unit rules,
+        // which never leads to a finite derivation.
+        if let Some(&depth) = self.visiting.get(&key) {
+            self.lowest_cut = self.lowest_cut.min(depth);
+            return None;
+        }
+
+        let depth = self.visiting.len();
+        self.visiting.insert(key, depth);
+        let outer_cut = std::mem::replace(&mut self.lowest_cut, usize::MAX);
+
+        let productions = self.chart.productions;
+        let derivation = productions[&rule]
+            .iter()
+            .enumerate()
+            .find_map(|(alternative, symbols)| {
+                self.build_sequence(symbols, start, end)
+                    .map(|children| Derivation {
+                        rule,
+                        alternative,
+                        span: self.chart.offset(start)..self.chart.offset(end),
+                        children,
+                    })
+            });
+
+        self.visiting.remove(&key);
+        // What was found only holds in general if no cycle was cut at an enclosing rule.
+        if self.lowest_cut >= depth {
+            self.memo.insert(key, derivation.clone());
+            self.lowest_cut = outer_cut;
+        } else {
+            self.lowest_cut = self.lowest_cut.min(outer_cut);
+        }
+        derivation
+    }
+
+    fn build_sequence(
+        &mut self,
+        symbols: &[Symbol],
+        start: usize,
+        end: usize,
+    ) -> Option<Vec<Derivation>> {
+        let (first, rest) = match symbols.split_first() {
+            Some(split) => split,
+            None => return if start == end { Some(Vec::new()) } else { None },
+        };
+
+        match *first {
+            Symbol::Char(c) => match self.chart.chars.get(start) {
+                Some(&(_, actual)) if actual == c && start < end => {
+                    self.build_sequence(rest, start + 1, end)
+                }
+                _ => None,
+            },
+            Symbol::Rule(r) => ((start + 1)..=end).find_map(|middle| {
+                let child = self.build(r, start, middle)?;
+                let mut children = self.build_sequence(rest, middle, end)?;
+                children.insert(0, child);
+                Some(children)
+            }),
+        }
+    }
+}
+
+// Remainders are listed longest match first.
+impl<'a> Matcher<&'a str> for Earley {
+    fn matches(&self, input: &'a str) -> MatchResult<&'a str> {
+        self.remainders(input).into_iter().next().ok_or(input)
+    }
+
+    fn remainders(&self, input: &'a str) -> Vec<&'a str> {
+        let chart = self.chart(input);
+        chart
+            .ends
+            .iter()
+            .rev()
+            .map(|&end| &input[chart.offset(end)..])
+            .collect()
+    }
+
+    fn exactly_matches(&self, input: &'a str) -> bool {
+        self.recognises(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::{create_matcher, extend_rules, parse_rules};
+
+    #[test]
+    fn left_recursion() {
+        let rules = parse_rules("0: 0 1 | 1\n1: \"a\"").unwrap();
+        let earley = Earley::new(0, &rules);
+
+        assert!(earley.recognises("a"));
+        assert!(earley.recognises("aaaa"));
+        assert!(!earley.recognises(""));
+        assert!(!earley.recognises("aab"));
+        assert_eq!(vec!["", "a", "aa"], earley.remainders("aaa"));
+    }
+
+    #[test]
+    fn ambiguous_and_cyclic_rules() {
+        let rules = parse_rules("0: 0 0 | 2\n2: 0 | 1\n1: \"b\"").unwrap();
+        let earley = Earley::new(0, &rules);
+
+        assert!(earley.recognises("bbbbb"));
+        let derivation = earley.parse("bbb").unwrap();
+        assert_eq!(0..3, derivation.span);
+    }
+
+    #[test]
+    fn cut_cycles_are_not_remembered() {
+        // The first alternative of 3 builds 0, which tries 2 and so 0 again, cutting that
+        // attempt short. When the alternative fails, 2 must still be able to derive "x".
+        let rules = parse_rules("0: 2 | 1\n2: 0\n1: \"x\"\n3: 0 4 | 2 1\n4: 1 1").unwrap();
+        let earley = Earley::new(3, &rules);
+
+        let derivation = earley.parse("xx").unwrap();
+        assert_eq!(1, derivation.alternative);
+        assert_eq!(vec![2, 1], derivation.children.iter().map(|c| c.rule).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn long_ambiguous_input() {
+        // Every split of the input is a derivation of 0, which is hopeless to search
+        // without remembering what each span derives.
+        let rules = parse_rules("0: 0 0 | 1\n1: \"a\"").unwrap();
+        let earley = Earley::new(0, &rules);
+        assert_eq!(None, earley.parse(&format!("{}b", "a".repeat(60))));
+        assert_eq!(0..60, earley.parse(&"a".repeat(60)).unwrap().span);
+    }
+
+    #[test]
+    fn reports_derivation() {
+        let rules = parse_rules(
+            "0: 4 1 5
+        1: 2 3 | 3 2
+        2: 4 4 | 5 5
+        3: 4 5 | 5 4
+        4: \"a\"
+        5: \"b\"",
+        ).unwrap();
+        let earley = Earley::new(0, &rules);
+
+        let derivation = earley.parse("ababbb").unwrap();
+        assert_eq!(
+            vec![(4, 0..1), (1, 1..5), (5, 5..6)],
+            derivation
+                .children
+                .iter()
+                .map(|child| (child.rule, child.span.clone()))
+                .collect::<Vec<_>>()
+        );
+        // "babb" is 3 ("ba") followed by 2 ("bb"), the second alternative of rule 1.
+        assert_eq!(1, derivation.children[1].alternative);
+        assert_eq!(
+            "0 (alternative 0) 0..6\n  4 (alternative 0) 0..1\n  1 (alternative 1) 1..5\n    \
+             3 (alternative 1) 1..3\n      5 (alternative 0) 1..2\n      4 (alternative 0) 2..3\n    \
+             2 (alternative 1) 3..5\n      5 (alternative 0) 3..4\n      5 (alternative 0) 4..5\n  \
+             5 (alternative 0) 5..6\n",
+            derivation.to_string()
+        );
+
+        assert_eq!(None, earley.parse("aaaabbb"));
+    }
+
+    #[test]
+    fn agrees_with_combinators_on_puzzle_input() {
+        let mut rules = parse_rules(include_str!("rules.txt")).unwrap();
+        extend_rules(&mut rules, "8: 42 | 42 8\n11: 42 31 | 42 11 31").unwrap();
+        let earley = Earley::new(0, &rules);
+        let matcher = create_matcher(0, &rules);
+
+        for message in include_str!("messages.txt").lines().take(40) {
+            assert_eq!(matcher.exactly_matches(message), earley.recognises(message), "{}", message);
+        }
+    }
+}
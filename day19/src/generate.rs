@@ -0,0 +1,194 @@
+use crate::parsing::Rule;
+use std::collections::{BTreeSet, HashMap};
+
+pub use rng::Rng;
+
+// Every message derivable from rule `index` using at most `max_depth` levels of rules,
+// where a literal rule counts as one level. The number of messages grows very quickly
+// with the depth, so keep it small for recursive rules.
+pub fn enumerate(
+    index: usize,
+    rules: &HashMap<usize, Rule>,
+    max_depth: usize,
+) -> BTreeSet<String> {
+    fn language(
+        index: usize,
+        depth: usize,
+        rules: &HashMap<usize, Rule>,
+        memo: &mut HashMap<(usize, usize), BTreeSet<String>>,
+    ) -> BTreeSet<String> {
+        if depth == 0 {
+            return BTreeSet::new();
+        }
+        if let Some(messages) = memo.get(&(index, depth)) {
+            return messages.clone();
+        }
+
+        let rule = rules.get(&index).expect("Looking for non-existent rule");
+        let mut messages = BTreeSet::new();
+        if let Rule::Literal(text) = rule {
+            messages.insert(text.clone());
+        }
+        for numbers in rule.alternatives() {
+            let mut prefixes: BTreeSet<String> = std::iter::once(String::new()).collect();
+            for &n in numbers {
+                let suffixes = language(n, depth - 1, rules, memo);
+                prefixes = prefixes
+                    .iter()
+                    .flat_map(|p| suffixes.iter().map(move |s| format!("{}{}", p, s)))
+                    .collect();
+            }
+            messages.extend(prefixes);
+        }
+
+        memo.insert((index, depth), messages.clone());
+        messages
+    }
+
+    language(index, max_depth, rules, &mut HashMap::new())
+}
+
+// The depth of the shallowest derivation from each rule, counted as in `enumerate`.
+// Rules that can't derive any finite message are missing.
+fn min_depths(rules: &HashMap<usize, Rule>) -> HashMap<usize, usize> {
+    let mut depths = HashMap::new();
+
+    loop {
+        let mut changed = false;
+        for (&index, rule) in rules {
+            let depth = match rule {
+                Rule::Literal(_) => Some(1),
+                _ => rule.alternatives()
+                    .into_iter()
+                    .filter_map(|numbers| {
+                        numbers
+                            .iter()
+                            .map(|n| depths.get(n).copied())
+                            .try_fold(0, |deepest: usize, d| d.map(|d| deepest.max(d)))
+                    })
+                    .min()
+                    .map(|deepest| deepest + 1),
+            };
+            if let Some(depth) = depth {
+                if depths.get(&index).map_or(true, |&d| depth < d) {
+                    depths.insert(index, depth);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return depths;
+        }
+    }
+}
+
+// A random message derivable from rule `index` within `max_depth` levels, or `None` if
+// there isn't one. Each rule picks uniformly among the alternatives that can still finish
+// in the depth remaining.
+pub fn sample(
+    index: usize,
+    rules: &HashMap<usize, Rule>,
+    max_depth: usize,
+    rng: &mut Rng,
+) -> Option<String> {
+    fn expand(
+        index: usize,
+        depth: usize,
+        rules: &HashMap<usize, Rule>,
+        min_depths: &HashMap<usize, usize>,
+        rng: &mut Rng,
+        message: &mut String,
+    ) {
+        let rule = &rules[&index];
+        if let Rule::Literal(text) = rule {
+            message.push_str(text);
+            return;
+        }
+
+        let fits = |n: &usize| min_depths.get(n).map_or(false, |&d| d < depth);
+        let candidates: Vec<&[usize]> = rule.alternatives()
+            .into_iter()
+            .filter(|numbers| numbers.iter().all(fits))
+            .collect();
+        let numbers = candidates[rng.below(candidates.len())];
+        for &n in numbers {
+            expand(n, depth - 1, rules, min_depths, rng, message);
+        }
+    }
+
+    let min_depths = min_depths(rules);
+    if min_depths.get(&index).map_or(true, |&d| d > max_depth) {
+        return None;
+    }
+
+    let mut message = String::new();
+    expand(index, max_depth, rules, &min_depths, rng, &mut message);
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::earley::Earley;
+    use crate::matching::Matcher;
+    use crate::parsing::{create_matcher, extend_rules, parse_rules};
+
+    #[test]
+    fn enumerates_example() {
+        let rules = parse_rules(
+            "0: 4 1 5
+        1: 2 3 | 3 2
+        2: 4 4 | 5 5
+        3: 4 5 | 5 4
+        4: \"a\"
+        5: \"b\"",
+        )
+        .unwrap();
+
+        let messages: Vec<String> = enumerate(0, &rules, 4).into_iter().collect();
+        assert_eq!(
+            vec!["aaaabb", "aaabab", "aabaab", "aabbbb", "abaaab", "ababbb", "abbabb", "abbbab"],
+            messages
+        );
+        assert!(enumerate(0, &rules, 3).is_empty());
+    }
+
+    #[test]
+    fn depth_bounds_recursion() {
+        let rules = parse_rules("0: 1 | 1 0\n1: \"ab\"").unwrap();
+        let messages: Vec<String> = enumerate(0, &rules, 4).into_iter().collect();
+        assert_eq!(vec!["ab", "abab", "ababab"], messages);
+
+        let mut rng = Rng::new(1);
+        assert_eq!(None, sample(0, &rules, 1, &mut rng));
+        assert_eq!(Some("ab".to_string()), sample(0, &rules, 2, &mut rng));
+        for _ in 0..20 {
+            assert!(sample(0, &rules, 6, &mut rng).unwrap().len() <= 10);
+        }
+    }
+
+    #[test]
+    fn rules_without_finite_messages() {
+        let rules = parse_rules("0: 1 | 2\n1: 1 2\n2: \"a\"").unwrap();
+        assert_eq!(Some("a".to_string()), sample(0, &rules, 5, &mut Rng::new(2)));
+        assert_eq!(None, sample(1, &rules, 5, &mut Rng::new(2)));
+        assert!(enumerate(1, &rules, 5).is_empty());
+    }
+
+    #[test]
+    fn samples_are_matched_by_every_matcher() {
+        let mut rules = parse_rules(include_str!("rules.txt")).unwrap();
+        extend_rules(&mut rules, "8: 42 | 42 8\n11: 42 31 | 42 11 31").unwrap();
+        let mut rng = Rng::new(3);
+        let messages: Vec<String> = (0..50)
+            .map(|_| sample(0, &rules, 20, &mut rng).unwrap())
+            .collect();
+
+        let matcher = create_matcher(0, &rules);
+        let earley = Earley::new(0, &rules);
+        for message in &messages {
+            assert!(matcher.exactly_matches(message), "{}", message);
+            assert!(earley.recognises(message), "{}", message);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use crate::matching::Matcher;
+use std::io::{self, BufRead};
+
+// Something matchers can consume from the front, one item at a time. Inputs are cheap to
+// copy so that matchers can keep several remainders of the same input around. `len` is
+// only ever compared between remainders of one input, to tell them apart and to measure
+// how far matching got; for strings it's in bytes.
+pub trait Input: Copy {
+    type Item: Copy + PartialEq;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn split_first(&self) -> Option<(Self::Item, Self)>;
+}
+
+impl Input for &str {
+    type Item = char;
+
+    fn len(&self) -> usize {
+        str::len(self)
+    }
+
+    fn split_first(&self) -> Option<(char, Self)> {
+        let c = self.chars().next()?;
+        Some((c, &self[c.len_utf8()..]))
+    }
+}
+
+// Covers byte strings as well as slices of tokens from a separate lexer.
+impl<T> Input for &[T]
+where
+    T: Copy + PartialEq,
+{
+    type Item = T;
+
+    fn len(&self) -> usize {
+        <[T]>::len(self)
+    }
+
+    fn split_first(&self) -> Option<(T, Self)> {
+        <[T]>::split_first(self).map(|(&first, rest)| (first, rest))
+    }
+}
+
+// The messages in `reader`, one per line, that `matcher` matches exactly. Lines are read
+// one at a time, so a large file never has to be held in memory at once. This needs a
+// matcher that can match lines of any lifetime: the combinators, rule tables of
+// `StrMatcher`s such as `create_matcher` builds, `Dfa` and `Earley` all can.
+pub fn matching_lines<R, M>(reader: R, matcher: M) -> impl Iterator<Item = io::Result<String>>
+where
+    R: BufRead,
+    M: for<'b> Matcher<&'b str>,
+{
+    reader.lines().filter(move |line| match line {
+        Ok(line) => matcher.exactly_matches(line),
+        Err(_) => true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::earley::Earley;
+    use crate::matching::{either, literal, one_or_more, pair, satisfy, sequence, tag, BoxedMatcher};
+    use crate::parsing::{create_matcher, extend_rules, parse_rules};
+    use crate::regular::Dfa;
+    use std::io::Cursor;
+
+    #[test]
+    fn matches_bytes() {
+        let m = pair(tag(b"ab"), one_or_more(either(literal(b'a'), literal(b'b'))));
+        assert_eq!(Ok(&b"c"[..]), m.matches(&b"abbac"[..]));
+        assert!(m.exactly_matches(&b"abab"[..]));
+        assert!(!m.exactly_matches(&b"ab"[..]));
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Token {
+        Number(u32),
+        Plus,
+    }
+
+    #[test]
+    fn matches_tokens() {
+        let tokens = [Token::Number(1), Token::Plus, Token::Number(2), Token::Plus];
+        let number = || satisfy(|t: Token| matches!(t, Token::Number(_)));
+        let sum: BoxedMatcher<&[Token]> = BoxedMatcher::new(sequence(vec![
+            BoxedMatcher::new(number()),
+            BoxedMatcher::new(one_or_more(pair(literal(Token::Plus), number()))),
+        ]));
+
+        assert_eq!(Ok(&tokens[3..]), sum.matches(&tokens[..]));
+        assert!(sum.exactly_matches(&tokens[..3]));
+        assert!(!sum.exactly_matches(&tokens[..1]));
+    }
+
+    #[test]
+    fn streams_lines() {
+        let reader = Cursor::new("ab\nabab\nba\n\nab\n");
+        let m = one_or_more(pair(literal('a'), literal('b')));
+        let lines: Vec<String> = matching_lines(reader, m).map(Result::unwrap).collect();
+        assert_eq!(vec!["ab", "abab", "ab"], lines);
+    }
+
+    #[test]
+    fn streams_puzzle_messages() {
+        let messages = include_str!("messages.txt");
+        let mut rules = parse_rules(include_str!("rules.txt")).unwrap();
+
+        let dfa = Dfa::compile(0, &rules).unwrap();
+        assert_eq!(178, matching_lines(Cursor::new(messages), dfa).count());
+
+        // Earley is slow to run over every message in a debug build.
+        let first_messages: Vec<&str> = messages.lines().take(40).collect();
+        extend_rules(&mut rules, "8: 42 | 42 8\n11: 42 31 | 42 11 31").unwrap();
+        let matcher = create_matcher(0, &rules);
+        let expected = first_messages.iter().filter(|m| matcher.exactly_matches(m)).count();
+
+        let earley = Earley::new(0, &rules);
+        let reader = Cursor::new(first_messages.join("\n"));
+        assert_eq!(expected, matching_lines(reader, earley).count());
+    }
+
+    #[test]
+    fn streams_with_rule_table() {
+        let rules = parse_rules(include_str!("rules.txt")).unwrap();
+        let messages = include_str!("messages.txt");
+        let matcher = create_matcher(0, &rules);
+        assert_eq!(178, matching_lines(Cursor::new(messages), matcher).count());
+
+        let mut rules = rules;
+        extend_rules(&mut rules, "8: 42 | 42 8\n11: 42 31 | 42 11 31").unwrap();
+        let matcher = create_matcher(0, &rules);
+        let expected = messages.lines().filter(|m| matcher.exactly_matches(m)).count();
+        let reader = Cursor::new(messages);
+        assert_eq!(expected, matching_lines(reader, create_matcher(0, &rules)).count());
+    }
+}
@@ -1,2 +1,9 @@
+pub mod analysis;
+pub mod earley;
+pub mod generate;
+pub mod input;
 pub mod matching;
+pub mod parse_tree;
+pub mod parser;
 pub mod parsing;
+pub mod regular;
@@ -1,13 +1,24 @@
-use day19::parsing::{parse_rules, create_matcher};
+use day19::analysis::prune;
+use day19::parsing::{extend_rules, parse_rules};
 use day19::matching::Matcher;
+use day19::regular::compile_matcher;
 fn main() {
-    let rules = parse_rules(include_str!("rules.txt"));
+    let mut rules = parse_rules(include_str!("rules.txt")).unwrap();
     let messages = include_str!("messages.txt");
-    let matcher = create_matcher(0, &rules);
+    let matcher = compile_matcher(0, &rules);
 
     let matching = messages.lines()
         .filter(|message| matcher.exactly_matches(message))
         .count();
 
     println!("{} messages exactly match rule 0.", matching);
+
+    extend_rules(&mut rules, "8: 42 | 42 8\n11: 42 31 | 42 11 31").unwrap();
+    let matcher = prune(compile_matcher(0, &rules), 0, &rules);
+
+    let matching = messages.lines()
+        .filter(|message| matcher.exactly_matches(message))
+        .count();
+
+    println!("With looping rules, {} messages exactly match rule 0.", matching);
 }
@@ -1,35 +1,59 @@
 // Based on https://bodil.lol/Matcher-combinators/
 
-pub type MatchResult<'a> = Result<&'a str, &'a str>;
+use crate::input::Input;
+use crate::parse_tree::{Diagnostics, Parse};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
 
-pub trait Matcher<'a> {
-    fn matches(&self, input: &'a str) -> MatchResult<'a>;
+pub type MatchResult<I> = Result<I, I>;
 
-    fn exactly_matches(&self, input: &'a str) -> bool {
-        match self.matches(input) {
-            Ok(remaining) => remaining.is_empty(),
-            Err(_) => false
+pub trait Matcher<I: Input> {
+    fn matches(&self, input: I) -> MatchResult<I>;
+
+    // Every possible remainder after matching, so that callers can backtrack into
+    // alternatives that `matches` would have committed to. Remainders are suffixes of
+    // `input` without duplicates. Each matcher picks their order, and `matches` gives the
+    // first of them: the combinators follow the order alternatives are declared in.
+    fn remainders(&self, input: I) -> Vec<I> {
+        self.matches(input).into_iter().collect()
+    }
+
+    fn exactly_matches(&self, input: I) -> bool {
+        self.remainders(input).iter().any(|remaining| remaining.is_empty())
+    }
+
+    // Like `remainders`, but also builds the parse trees for each way of matching and
+    // records failures in `diagnostics`. See `parse_tree::parse_exact`.
+    fn parses(&self, input: I, diagnostics: &mut Diagnostics) -> Vec<Parse<I>> {
+        let remainders = self.remainders(input);
+        if remainders.is_empty() {
+            diagnostics.leaf_failed(input);
         }
+        remainders.into_iter().map(Parse::leaf).collect()
     }
 }
 
-impl<'a, F> Matcher<'a> for F
+impl<I, F> Matcher<I> for F
 where
-    F: Fn(&'a str) -> MatchResult<'a>,
+    I: Input,
+    F: Fn(I) -> MatchResult<I>,
 {
-    fn matches(&self, input: &'a str) -> MatchResult<'a> {
+    fn matches(&self, input: I) -> MatchResult<I> {
         self(input)
     }
 }
 
-pub struct BoxedMatcher<'a> {
-    matcher: Box<dyn Matcher<'a> + 'a>,
+// A matcher for one type of input, boxed so that different matchers can be stored
+// together. The box lives for `'m`, independently of the input it's given.
+pub struct BoxedMatcher<'m, I: Input> {
+    matcher: Box<dyn Matcher<I> + 'm>,
 }
 
-impl<'a> BoxedMatcher<'a> {
+impl<'m, I: Input> BoxedMatcher<'m, I> {
     pub fn new<P>(matcher: P) -> Self
     where
-        P: Matcher<'a> + 'a,
+        P: Matcher<I> + 'm,
     {
         BoxedMatcher {
             matcher: Box::new(matcher),
@@ -37,42 +61,599 @@ impl<'a> BoxedMatcher<'a> {
     }
 }
 
-impl<'a> Matcher<'a> for BoxedMatcher<'a> {
-    fn matches(&self, input: &'a str) -> MatchResult<'a> {
+impl<'m, I: Input> Matcher<I> for BoxedMatcher<'m, I> {
+    fn matches(&self, input: I) -> MatchResult<I> {
         self.matcher.matches(input)
     }
+
+    fn remainders(&self, input: I) -> Vec<I> {
+        self.matcher.remainders(input)
+    }
+
+    fn parses(&self, input: I, diagnostics: &mut Diagnostics) -> Vec<Parse<I>> {
+        self.matcher.parses(input, diagnostics)
+    }
+}
+
+// Like `BoxedMatcher`, but for string matchers that accept strings of any lifetime, so
+// that one matcher can be used on each line of a stream in turn.
+pub struct StrMatcher<'m> {
+    matcher: Box<dyn for<'b> Matcher<&'b str> + 'm>,
 }
 
-pub fn literal<'a>(value: char) -> impl Fn(&'a str) -> MatchResult<'a> {
-    move |input| {
-        if input.starts_with(value) {
-            Ok(&input[value.len_utf8()..])
-        } else {
-            Err(input)
+impl<'m> StrMatcher<'m> {
+    pub fn new<P>(matcher: P) -> Self
+    where
+        P: for<'b> Matcher<&'b str> + 'm,
+    {
+        StrMatcher {
+            matcher: Box::new(matcher),
         }
     }
 }
 
-pub fn either<'a, P1, P2>(a: P1, b: P2) -> impl Matcher<'a>
+impl<'b, 'm> Matcher<&'b str> for StrMatcher<'m> {
+    fn matches(&self, input: &'b str) -> MatchResult<&'b str> {
+        self.matcher.matches(input)
+    }
+
+    fn remainders(&self, input: &'b str) -> Vec<&'b str> {
+        self.matcher.remainders(input)
+    }
+
+    fn parses(&self, input: &'b str, diagnostics: &mut Diagnostics) -> Vec<Parse<&'b str>> {
+        self.matcher.parses(input, diagnostics)
+    }
+}
+
+impl<I, M> Matcher<I> for Rc<M>
 where
-    P1: Matcher<'a>,
-    P2: Matcher<'a>,
+    I: Input,
+    M: Matcher<I> + ?Sized,
 {
-    move |input| match a.matches(input) {
-        Ok(value) => Ok(value),
-        Err(_) => b.matches(input),
+    fn matches(&self, input: I) -> MatchResult<I> {
+        (**self).matches(input)
+    }
+
+    fn remainders(&self, input: I) -> Vec<I> {
+        (**self).remainders(input)
+    }
+
+    fn parses(&self, input: I, diagnostics: &mut Diagnostics) -> Vec<Parse<I>> {
+        (**self).parses(input, diagnostics)
+    }
+}
+
+// Matchers indexed by rule number. Rules refer to each other through `RuleRef`, which
+// looks the target up when matching rather than when building, so building the table
+// takes one pass over the rules and cyclic rules are fine. `M` is the boxed matcher type,
+// such as `BoxedMatcher` or `StrMatcher`.
+pub struct RuleTable<M> {
+    matchers: HashMap<usize, M>,
+    // The rules being matched, each with the length of its input. Only as long as the
+    // nesting of rules, so a search is quicker than hashing.
+    active: RefCell<Vec<(usize, usize)>>,
+}
+
+impl<M> RuleTable<M> {
+    // `create` is given a handle to the table under construction for use with `rule_ref`.
+    pub fn new<F>(create: F) -> Rc<Self>
+    where
+        F: FnOnce(&Weak<RuleTable<M>>) -> HashMap<usize, M>,
+    {
+        Rc::new_cyclic(|table| RuleTable {
+            matchers: create(table),
+            active: RefCell::new(Vec::new()),
+        })
+    }
+
+    pub fn get(&self, index: usize) -> Option<&M> {
+        self.matchers.get(&index)
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.matchers.contains_key(&index)
+    }
+
+    // Runs `f` on rule `index`, or returns `None` rather than matching the rule again at
+    // the same point of the input. Only left recursion does that, and it would otherwise
+    // never return, so such rules match only through their other alternatives; `Earley`
+    // handles them fully.
+    fn with_rule<I, T, F>(&self, index: usize, input: I, f: F) -> Option<T>
+    where
+        I: Input,
+        F: FnOnce(&M) -> T,
+    {
+        let matcher = self.get(index).expect("Looking for non-existent rule");
+        let key = (index, input.len());
+        if self.active.borrow().contains(&key) {
+            return None;
+        }
+        self.active.borrow_mut().push(key);
+        let result = f(matcher);
+        self.active.borrow_mut().pop();
+        Some(result)
+    }
+}
+
+// Only holds a weak reference so that cyclic rules don't keep the table alive forever;
+// whoever matches against the table must own it, as `TableMatcher` does.
+pub struct RuleRef<M> {
+    index: usize,
+    table: Weak<RuleTable<M>>,
+}
+
+pub fn rule_ref<M>(index: usize, table: &Weak<RuleTable<M>>) -> RuleRef<M> {
+    RuleRef {
+        index,
+        table: table.clone(),
+    }
+}
+
+impl<M> RuleRef<M> {
+    fn with_rule<I, T, F>(&self, input: I, f: F) -> Option<T>
+    where
+        I: Input,
+        F: FnOnce(&M) -> T,
+    {
+        let table = self.table.upgrade().expect("Rule table has been dropped");
+        table.with_rule(self.index, input, f)
+    }
+}
+
+impl<I: Input, M: Matcher<I>> Matcher<I> for RuleRef<M> {
+    fn matches(&self, input: I) -> MatchResult<I> {
+        self.with_rule(input, |rule| rule.matches(input))
+            .unwrap_or(Err(input))
+    }
+
+    fn remainders(&self, input: I) -> Vec<I> {
+        self.with_rule(input, |rule| rule.remainders(input))
+            .unwrap_or_default()
+    }
+
+    fn parses(&self, input: I, diagnostics: &mut Diagnostics) -> Vec<Parse<I>> {
+        let parses = self
+            .with_rule(input, |rule| rule.parses(input, diagnostics))
+            .unwrap_or_default();
+        rule_parses(self.index, input, parses, diagnostics)
     }
 }
 
-pub fn pair<'a, P1, P2>(a: P1, b: P2) -> impl Matcher<'a>
+// Matches one rule of a table and keeps the whole table alive.
+pub struct TableMatcher<M> {
+    index: usize,
+    table: Rc<RuleTable<M>>,
+}
+
+impl<M> TableMatcher<M> {
+    pub fn new(index: usize, table: Rc<RuleTable<M>>) -> Self {
+        TableMatcher { index, table }
+    }
+}
+
+impl<I: Input, M: Matcher<I>> Matcher<I> for TableMatcher<M> {
+    fn matches(&self, input: I) -> MatchResult<I> {
+        self.table
+            .with_rule(self.index, input, |rule| rule.matches(input))
+            .unwrap_or(Err(input))
+    }
+
+    fn remainders(&self, input: I) -> Vec<I> {
+        self.table
+            .with_rule(self.index, input, |rule| rule.remainders(input))
+            .unwrap_or_default()
+    }
+
+    fn parses(&self, input: I, diagnostics: &mut Diagnostics) -> Vec<Parse<I>> {
+        let parses = self
+            .table
+            .with_rule(self.index, input, |rule| rule.parses(input, diagnostics))
+            .unwrap_or_default();
+        rule_parses(self.index, input, parses, diagnostics)
+    }
+}
+
+fn rule_parses<I: Input>(
+    rule: usize,
+    input: I,
+    parses: Vec<Parse<I>>,
+    diagnostics: &mut Diagnostics,
+) -> Vec<Parse<I>> {
+    if parses.is_empty() {
+        diagnostics.rule_failed(rule, input);
+    }
+    parses
+        .into_iter()
+        .map(|parse| parse.into_node(rule, input))
+        .collect()
+}
+
+// The leaf matchers are structs rather than closures so that, like the combinators, they
+// work for inputs of any lifetime, which `input::matching_lines` relies on.
+pub struct Literal<T> {
+    value: T,
+}
+
+impl<I: Input> Matcher<I> for Literal<I::Item> {
+    fn matches(&self, input: I) -> MatchResult<I> {
+        match input.split_first() {
+            Some((item, rest)) if item == self.value => Ok(rest),
+            _ => Err(input),
+        }
+    }
+}
+
+pub fn literal<T>(value: T) -> Literal<T> {
+    Literal { value }
+}
+
+pub struct Tag<T> {
+    items: Vec<T>,
+}
+
+impl<I: Input> Matcher<I> for Tag<I::Item> {
+    fn matches(&self, input: I) -> MatchResult<I> {
+        let mut rest = input;
+        for &expected in &self.items {
+            match rest.split_first() {
+                Some((item, next)) if item == expected => rest = next,
+                _ => return Err(input),
+            }
+        }
+        Ok(rest)
+    }
+}
+
+// Matches `items` in order.
+pub fn tag<T: Clone>(items: &[T]) -> Tag<T> {
+    Tag {
+        items: items.to_vec(),
+    }
+}
+
+pub fn string(value: &str) -> Tag<char> {
+    Tag {
+        items: value.chars().collect(),
+    }
+}
+
+pub struct Satisfy<F> {
+    predicate: F,
+}
+
+impl<I, F> Matcher<I> for Satisfy<F>
+where
+    I: Input,
+    F: Fn(I::Item) -> bool,
+{
+    fn matches(&self, input: I) -> MatchResult<I> {
+        match input.split_first() {
+            Some((item, rest)) if (self.predicate)(item) => Ok(rest),
+            _ => Err(input),
+        }
+    }
+}
+
+// Matches any single item for which `predicate` holds.
+pub fn satisfy<F>(predicate: F) -> Satisfy<F> {
+    Satisfy { predicate }
+}
+
+// Like `satisfy`, but fixes the item type to `char` so that closures need no annotations.
+pub fn character<F>(predicate: F) -> Satisfy<F>
 where
-    P1: Matcher<'a>,
-    P2: Matcher<'a>,
+    F: Fn(char) -> bool,
 {
-    move |input| {
-        a.matches(input)
-            .and_then(|rest| b.matches(rest))
-            .map_err(|_| input)
+    Satisfy { predicate }
+}
+
+pub fn one_of(characters: &str) -> Satisfy<impl Fn(char) -> bool> {
+    let characters = characters.to_string();
+    character(move |c| characters.contains(c))
+}
+
+pub struct Either<P1, P2> {
+    a: P1,
+    b: P2,
+}
+
+impl<I, P1, P2> Matcher<I> for Either<P1, P2>
+where
+    I: Input,
+    P1: Matcher<I>,
+    P2: Matcher<I>,
+{
+    fn matches(&self, input: I) -> MatchResult<I> {
+        first(self.remainders(input), input)
+    }
+
+    fn remainders(&self, input: I) -> Vec<I> {
+        let mut remainders = self.a.remainders(input);
+        for remaining in self.b.remainders(input) {
+            push_unique(&mut remainders, remaining);
+        }
+        remainders
+    }
+
+    fn parses(&self, input: I, diagnostics: &mut Diagnostics) -> Vec<Parse<I>> {
+        let mut parses = self.a.parses(input, diagnostics);
+        for parse in self.b.parses(input, diagnostics) {
+            push_unique_parse(&mut parses, parse);
+        }
+        parses
+    }
+}
+
+pub fn either<P1, P2>(a: P1, b: P2) -> Either<P1, P2> {
+    Either { a, b }
+}
+
+pub struct Pair<P1, P2> {
+    a: P1,
+    b: P2,
+}
+
+impl<I, P1, P2> Matcher<I> for Pair<P1, P2>
+where
+    I: Input,
+    P1: Matcher<I>,
+    P2: Matcher<I>,
+{
+    fn matches(&self, input: I) -> MatchResult<I> {
+        first(self.remainders(input), input)
+    }
+
+    fn remainders(&self, input: I) -> Vec<I> {
+        let mut remainders = Vec::new();
+        for rest in self.a.remainders(input) {
+            for remaining in self.b.remainders(rest) {
+                push_unique(&mut remainders, remaining);
+            }
+        }
+        remainders
+    }
+
+    fn parses(&self, input: I, diagnostics: &mut Diagnostics) -> Vec<Parse<I>> {
+        let mut parses = Vec::new();
+        for first in self.a.parses(input, diagnostics) {
+            for parse in then(&first, &self.b, diagnostics) {
+                push_unique_parse(&mut parses, parse);
+            }
+        }
+        parses
+    }
+}
+
+pub fn pair<P1, P2>(a: P1, b: P2) -> Pair<P1, P2> {
+    Pair { a, b }
+}
+
+pub struct Optional<M> {
+    matcher: M,
+}
+
+impl<I, M> Matcher<I> for Optional<M>
+where
+    I: Input,
+    M: Matcher<I>,
+{
+    fn matches(&self, input: I) -> MatchResult<I> {
+        Ok(self.matcher.matches(input).unwrap_or(input))
+    }
+
+    fn remainders(&self, input: I) -> Vec<I> {
+        let mut remainders = self.matcher.remainders(input);
+        push_unique(&mut remainders, input);
+        remainders
+    }
+
+    fn parses(&self, input: I, diagnostics: &mut Diagnostics) -> Vec<Parse<I>> {
+        let mut parses = self.matcher.parses(input, diagnostics);
+        push_unique_parse(&mut parses, Parse::leaf(input));
+        parses
+    }
+}
+
+pub fn optional<M>(matcher: M) -> Optional<M> {
+    Optional { matcher }
+}
+
+// Repeats `matcher` at least `min` times. Remainders are listed longest match first,
+// so `matches` is greedy. Repetitions that consume nothing are ignored, since they
+// could go on forever.
+pub struct Repeat<M> {
+    matcher: M,
+    min: usize,
+}
+
+impl<I, M> Matcher<I> for Repeat<M>
+where
+    I: Input,
+    M: Matcher<I>,
+{
+    fn matches(&self, input: I) -> MatchResult<I> {
+        first(self.remainders(input), input)
+    }
+
+    fn remainders(&self, input: I) -> Vec<I> {
+        let mut remainders = Vec::new();
+        let mut frontier = vec![input];
+        let mut count = 0;
+
+        while !frontier.is_empty() {
+            if count >= self.min {
+                for &rest in &frontier {
+                    push_unique(&mut remainders, rest);
+                }
+            }
+
+            let mut next = Vec::new();
+            for &rest in &frontier {
+                for remaining in self.matcher.remainders(rest) {
+                    if remaining.len() < rest.len() {
+                        push_unique(&mut next, remaining);
+                    }
+                }
+            }
+
+            frontier = next;
+            count += 1;
+        }
+
+        remainders.sort_by_key(|remaining| remaining.len());
+        remainders
+    }
+
+    fn parses(&self, input: I, diagnostics: &mut Diagnostics) -> Vec<Parse<I>> {
+        let mut parses = Vec::new();
+        let mut frontier = vec![Parse::leaf(input)];
+        let mut count = 0;
+
+        while !frontier.is_empty() {
+            if count >= self.min {
+                for parse in &frontier {
+                    push_unique_parse(&mut parses, parse.clone());
+                }
+            }
+
+            let mut next = Vec::new();
+            for parse in &frontier {
+                for longer in then(parse, &self.matcher, diagnostics) {
+                    if longer.remaining.len() < parse.remaining.len() {
+                        push_unique_parse(&mut next, longer);
+                    }
+                }
+            }
+
+            frontier = next;
+            count += 1;
+        }
+
+        parses.sort_by_key(|parse| parse.remaining.len());
+        parses
+    }
+}
+
+pub fn zero_or_more<M>(matcher: M) -> Repeat<M> {
+    Repeat { matcher, min: 0 }
+}
+
+pub fn one_or_more<M>(matcher: M) -> Repeat<M> {
+    Repeat { matcher, min: 1 }
+}
+
+pub struct Sequence<M> {
+    matchers: Vec<M>,
+}
+
+impl<I: Input, M: Matcher<I>> Matcher<I> for Sequence<M> {
+    fn matches(&self, input: I) -> MatchResult<I> {
+        first(self.remainders(input), input)
+    }
+
+    fn remainders(&self, input: I) -> Vec<I> {
+        self.matchers
+            .iter()
+            .fold(vec![input], |remainders, matcher| {
+                let mut next = Vec::new();
+                for rest in remainders {
+                    for remaining in matcher.remainders(rest) {
+                        push_unique(&mut next, remaining);
+                    }
+                }
+                next
+            })
+    }
+
+    fn parses(&self, input: I, diagnostics: &mut Diagnostics) -> Vec<Parse<I>> {
+        self.matchers
+            .iter()
+            .fold(vec![Parse::leaf(input)], |parses, matcher| {
+                let mut next = Vec::new();
+                for parse in &parses {
+                    for longer in then(parse, matcher, diagnostics) {
+                        push_unique_parse(&mut next, longer);
+                    }
+                }
+                next
+            })
+    }
+}
+
+pub fn sequence<M>(matchers: Vec<M>) -> Sequence<M> {
+    Sequence { matchers }
+}
+
+pub struct Choice<M> {
+    matchers: Vec<M>,
+}
+
+impl<I: Input, M: Matcher<I>> Matcher<I> for Choice<M> {
+    fn matches(&self, input: I) -> MatchResult<I> {
+        first(self.remainders(input), input)
+    }
+
+    fn remainders(&self, input: I) -> Vec<I> {
+        let mut remainders = Vec::new();
+        for matcher in &self.matchers {
+            for remaining in matcher.remainders(input) {
+                push_unique(&mut remainders, remaining);
+            }
+        }
+        remainders
+    }
+
+    fn parses(&self, input: I, diagnostics: &mut Diagnostics) -> Vec<Parse<I>> {
+        let mut parses = Vec::new();
+        for matcher in &self.matchers {
+            for parse in matcher.parses(input, diagnostics) {
+                push_unique_parse(&mut parses, parse);
+            }
+        }
+        parses
+    }
+}
+
+pub fn choice<M>(matchers: Vec<M>) -> Choice<M> {
+    Choice { matchers }
+}
+
+// Continues each way `parse` matched with `matcher`, keeping the trees from both.
+fn then<I, M>(parse: &Parse<I>, matcher: &M, diagnostics: &mut Diagnostics) -> Vec<Parse<I>>
+where
+    I: Input,
+    M: Matcher<I> + ?Sized,
+{
+    matcher
+        .parses(parse.remaining, diagnostics)
+        .into_iter()
+        .map(|next| {
+            let mut trees = parse.trees.clone();
+            trees.extend(next.trees);
+            Parse {
+                remaining: next.remaining,
+                trees,
+            }
+        })
+        .collect()
+}
+
+fn first<I>(remainders: Vec<I>, input: I) -> MatchResult<I> {
+    remainders.into_iter().next().ok_or(input)
+}
+
+// Remainders are always suffixes of the same input, so their lengths identify them.
+fn push_unique<I: Input>(remainders: &mut Vec<I>, remaining: I) {
+    if remainders.iter().all(|r| r.len() != remaining.len()) {
+        remainders.push(remaining);
+    }
+}
+
+// Keeps only the first parse for each remainder, mirroring `push_unique`.
+fn push_unique_parse<I: Input>(parses: &mut Vec<Parse<I>>, parse: Parse<I>) {
+    if parses.iter().all(|p| p.remaining.len() != parse.remaining.len()) {
+        parses.push(parse);
     }
 }
 
@@ -81,13 +662,13 @@ mod tests {
     use super::*;
     #[test]
     fn test_literal_ok() {
-        let result = literal('a')("abc");
+        let result = literal('a').matches("abc");
         assert_eq!(result, Ok("bc"));
     }
 
     #[test]
     fn test_literal_error() {
-        let result = literal('b')("abc");
+        let result = literal('b').matches("abc");
         assert_eq!(result, Err("abc"));
     }
 
@@ -117,4 +698,100 @@ mod tests {
         assert_eq!(Ok("cde"), p.matches("abcde"));
         assert_eq!(Err("acbde"), p.matches("acbde"));
     }
+
+    #[test]
+    fn test_either_keeps_every_alternative() {
+        let ab = pair(literal('a'), literal('b'));
+        let m = either(literal('a'), ab);
+        assert_eq!(vec!["bc", "c"], m.remainders("abc"));
+        assert_eq!(Ok("bc"), m.matches("abc"));
+    }
+
+    #[test]
+    fn test_pair_backtracks_into_first_matcher() {
+        // `either` on its own would commit to the single 'a' and then fail on 'b'.
+        let a_or_aa = either(literal('a'), pair(literal('a'), literal('a')));
+        let p = pair(a_or_aa, literal('b'));
+        assert_eq!(Ok(""), p.matches("aab"));
+        assert!(p.exactly_matches("aab"));
+    }
+
+    #[test]
+    fn test_string_and_character_classes() {
+        assert_eq!(Ok("cd"), string("ab").matches("abcd"));
+        assert_eq!(Err("acd"), string("ab").matches("acd"));
+        assert_eq!(Ok("x"), character(|c| c.is_ascii_digit()).matches("7x"));
+        assert_eq!(Ok("c"), one_of("ab").matches("bc"));
+        assert_eq!(Err("cb"), one_of("ab").matches("cb"));
+    }
+
+    #[test]
+    fn test_optional() {
+        let m = optional(literal('-'));
+        assert_eq!(vec!["1", "-1"], m.remainders("-1"));
+        assert_eq!(Ok("1"), m.matches("1"));
+    }
+
+    #[test]
+    fn test_repetition_is_greedy_but_backtracks() {
+        let digits = one_or_more(character(|c| c.is_ascii_digit()));
+        assert_eq!(vec!["x", "3x", "23x"], digits.remainders("123x"));
+        assert_eq!(Err("x"), digits.matches("x"));
+        assert_eq!(Ok("x"), zero_or_more(literal('a')).matches("x"));
+
+        // The last 'a' has to be given back for the trailing literal to match.
+        let p = pair(one_or_more(literal('a')), literal('a'));
+        assert!(p.exactly_matches("aaa"));
+        assert!(!p.exactly_matches("a"));
+
+        // Remainders are found without building parses, but must agree with them.
+        let parses = digits.parses("123x", &mut Diagnostics::new());
+        let remaining: Vec<&str> = parses.iter().map(|parse| parse.remaining).collect();
+        assert_eq!(digits.remainders("123x"), remaining);
+    }
+
+    #[test]
+    fn test_repetition_of_empty_match_terminates() {
+        let m = zero_or_more(optional(literal('a')));
+        assert_eq!(vec!["", "a", "aa"], m.remainders("aa"));
+    }
+
+    #[test]
+    fn test_sequence_and_choice() {
+        let m = sequence(vec![
+            BoxedMatcher::new(literal('a')),
+            BoxedMatcher::new(choice(vec![
+                BoxedMatcher::new(string("bc")),
+                BoxedMatcher::new(literal('b')),
+            ])),
+            BoxedMatcher::new(literal('c')),
+        ]);
+        assert!(m.exactly_matches("abc"));
+        assert!(m.exactly_matches("abcc"));
+        assert!(!m.exactly_matches("ac"));
+        assert!(sequence(Vec::<BoxedMatcher<&str>>::new()).exactly_matches(""));
+        assert!(!choice(Vec::<BoxedMatcher<&str>>::new()).exactly_matches(""));
+    }
+
+    #[test]
+    fn test_rule_table_allows_recursion() {
+        // 0 := 1 | 1 0, 1 := 'a'
+        let table = RuleTable::new(|table| {
+            let mut matchers = HashMap::new();
+            matchers.insert(
+                0,
+                BoxedMatcher::new(either(
+                    rule_ref(1, table),
+                    pair(rule_ref(1, table), rule_ref(0, table)),
+                )),
+            );
+            matchers.insert(1, BoxedMatcher::new(literal('a')));
+            matchers
+        });
+        let rule = TableMatcher::new(0, table);
+
+        assert_eq!(vec!["aab", "ab", "b"], rule.remainders("aaab"));
+        assert!(rule.exactly_matches("aaaa"));
+        assert!(!rule.exactly_matches("aaba"));
+    }
 }
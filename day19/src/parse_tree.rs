@@ -0,0 +1,239 @@
+use crate::input::Input;
+use crate::matching::Matcher;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Range;
+
+// A rule that matched part of the input. Only matchers that know which rule they stand
+// for (`RuleRef` and `TableMatcher`) produce nodes; other combinators pass their
+// children's nodes through.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseTree {
+    pub rule: usize,
+    pub span: Range<usize>,
+    pub children: Vec<ParseTree>,
+}
+
+// One way of matching the start of an input. Matchers only ever see the rest of the input,
+// so while matching, spans in `trees` count bytes back from the end of the input rather
+// than forward from its start; `parse_exact` converts them.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Parse<I> {
+    pub remaining: I,
+    pub trees: Vec<ParseTree>,
+}
+
+impl<I: Input> Parse<I> {
+    pub fn leaf(remaining: I) -> Self {
+        Parse {
+            remaining,
+            trees: Vec::new(),
+        }
+    }
+
+    // Wraps everything matched from `input` to `self.remaining` in a node for `rule`.
+    pub fn into_node(self, rule: usize, input: I) -> Self {
+        Parse {
+            remaining: self.remaining,
+            trees: vec![ParseTree {
+                rule,
+                span: input.len()..self.remaining.len(),
+                children: self.trees,
+            }],
+        }
+    }
+}
+
+impl ParseTree {
+    fn anchor(self, total: usize) -> ParseTree {
+        ParseTree {
+            rule: self.rule,
+            span: (total - self.span.start)..(total - self.span.end),
+            children: self.children.into_iter().map(|c| c.anchor(total)).collect(),
+        }
+    }
+}
+
+// Records the furthest point any matcher failed to match, and which rules failed there.
+// Positions are in the units of `Input::len`, so bytes for strings.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    furthest: Option<usize>,
+    expected: BTreeSet<usize>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Diagnostics::default()
+    }
+
+    // `remaining` is the input left at the point where `rule` failed to match.
+    pub fn rule_failed<I: Input>(&mut self, rule: usize, remaining: I) {
+        if self.reached(remaining) {
+            self.expected.insert(rule);
+        }
+    }
+
+    // For matchers that don't stand for a rule, such as literals and closures. A rule
+    // wrapping the matcher adds itself at the same position.
+    pub fn leaf_failed<I: Input>(&mut self, remaining: I) {
+        self.reached(remaining);
+    }
+
+    // Moves the furthest failure on to `remaining` if it's at least as far, and returns
+    // whether it is now there.
+    fn reached<I: Input>(&mut self, remaining: I) -> bool {
+        let remaining = remaining.len();
+        match self.furthest {
+            Some(furthest) if remaining > furthest => false,
+            Some(furthest) if remaining == furthest => true,
+            _ => {
+                self.furthest = Some(remaining);
+                self.expected.clear();
+                true
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub position: usize,
+    pub expected_rules: Vec<usize>,
+    pub expected_end: bool,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "No match at byte {}", self.position)?;
+        let mut expected: Vec<String> = self
+            .expected_rules
+            .iter()
+            .map(|rule| format!("rule {}", rule))
+            .collect();
+        if self.expected_end {
+            expected.push("end of input".to_string());
+        }
+        if expected.is_empty() {
+            return Ok(());
+        }
+        write!(f, ": expected {}", expected.join(" or "))
+    }
+}
+
+// Matches the whole of `input` and returns the parse trees, or reports how far matching got.
+pub fn parse_exact<I, M>(matcher: &M, input: I) -> Result<Vec<ParseTree>, Diagnostic>
+where
+    I: Input,
+    M: Matcher<I> + ?Sized,
+{
+    let mut diagnostics = Diagnostics::new();
+    let parses = matcher.parses(input, &mut diagnostics);
+    let total = input.len();
+
+    if let Some(parse) = parses.iter().find(|p| p.remaining.is_empty()) {
+        return Ok(parse.trees.iter().cloned().map(|t| t.anchor(total)).collect());
+    }
+
+    // A partial match which got further than any failure means the input ran on too long.
+    let shortest_remainder = parses.iter().map(|p| p.remaining.len()).min();
+    match diagnostics.furthest {
+        Some(furthest) if shortest_remainder.map_or(true, |r| r >= furthest) => Err(Diagnostic {
+            position: total - furthest,
+            expected_rules: diagnostics.expected.into_iter().collect(),
+            expected_end: shortest_remainder == Some(furthest),
+        }),
+        _ => Err(Diagnostic {
+            position: total - shortest_remainder.unwrap_or(total),
+            expected_rules: Vec::new(),
+            expected_end: shortest_remainder.is_some(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching::{literal, pair};
+    use crate::parsing::{create_matcher, parse_rules};
+
+    const EXAMPLE: &str = "0: 4 1 5
+        1: 2 3 | 3 2
+        2: 4 4 | 5 5
+        3: 4 5 | 5 4
+        4: \"a\"
+        5: \"b\"";
+
+    #[test]
+    fn builds_tree_with_spans() {
+        let rules = parse_rules(EXAMPLE).unwrap();
+        let matcher = create_matcher(0, &rules);
+
+        let trees = parse_exact(&matcher, "ababbb").unwrap();
+        assert_eq!(1, trees.len());
+        let root = &trees[0];
+        assert_eq!((0, 0..6), (root.rule, root.span.clone()));
+        assert_eq!(
+            vec![(4, 0..1), (1, 1..5), (5, 5..6)],
+            root.children
+                .iter()
+                .map(|c| (c.rule, c.span.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(3, 1..3), (2, 3..5)],
+            root.children[1]
+                .children
+                .iter()
+                .map(|c| (c.rule, c.span.clone()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn reports_furthest_failure() {
+        let rules = parse_rules(EXAMPLE).unwrap();
+        let matcher = create_matcher(0, &rules);
+
+        // After "a", "aa" and the "a" that starts rule 3, a "b" is needed at byte 4.
+        let diagnostic = parse_exact(&matcher, "aaaaaa").unwrap_err();
+        assert_eq!(4, diagnostic.position);
+        assert_eq!(vec![5], diagnostic.expected_rules);
+        assert_eq!("No match at byte 4: expected rule 5", diagnostic.to_string());
+    }
+
+    #[test]
+    fn reports_trailing_input() {
+        let rules = parse_rules(EXAMPLE).unwrap();
+        let matcher = create_matcher(0, &rules);
+
+        let diagnostic = parse_exact(&matcher, "aaaabbb").unwrap_err();
+        assert_eq!(6, diagnostic.position);
+        assert!(diagnostic.expected_end);
+    }
+
+    #[test]
+    fn combinators_without_rules_produce_no_nodes() {
+        let matcher = pair(literal('a'), literal('b'));
+        assert_eq!(Ok(Vec::new()), parse_exact(&matcher, "ab"));
+
+        let diagnostic = parse_exact(&matcher, "ac").unwrap_err();
+        assert_eq!(1, diagnostic.position);
+        assert_eq!("No match at byte 1", diagnostic.to_string());
+    }
+
+    #[test]
+    fn closures_report_where_they_failed() {
+        fn digit(input: &str) -> Result<&str, &str> {
+            match input.chars().next() {
+                Some(c) if c.is_ascii_digit() => Ok(&input[1..]),
+                _ => Err(input),
+            }
+        }
+        let matcher = pair(digit, pair(digit, digit));
+
+        let diagnostic = parse_exact(&matcher, "12x").unwrap_err();
+        assert_eq!(2, diagnostic.position);
+        assert!(!diagnostic.expected_end);
+    }
+}
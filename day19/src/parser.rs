@@ -0,0 +1,153 @@
+use crate::matching::Matcher;
+
+// Parsers that produce a value as well as consuming input. Unlike matchers they commit to
+// the first way they find of matching, which is what puzzle input formats need. On failure
+// the error is the input at the point the parser gave up.
+pub type ParseResult<'a, T> = Result<(&'a str, T), &'a str>;
+
+pub trait Parser<'a, T> {
+    fn parse(&self, input: &'a str) -> ParseResult<'a, T>;
+}
+
+impl<'a, F, T> Parser<'a, T> for F
+where
+    F: Fn(&'a str) -> ParseResult<'a, T>,
+{
+    fn parse(&self, input: &'a str) -> ParseResult<'a, T> {
+        self(input)
+    }
+}
+
+// The text a matcher consumed, using the matcher's preferred remainder.
+pub fn recognize<'a, M>(matcher: M) -> impl Fn(&'a str) -> ParseResult<'a, &'a str>
+where
+    M: Matcher<&'a str>,
+{
+    move |input| {
+        let remaining = matcher.matches(input)?;
+        Ok((remaining, &input[..input.len() - remaining.len()]))
+    }
+}
+
+pub fn map<'a, P, F, A, B>(parser: P, f: F) -> impl Fn(&'a str) -> ParseResult<'a, B>
+where
+    P: Parser<'a, A>,
+    F: Fn(A) -> B,
+{
+    move |input| parser.parse(input).map(|(rest, value)| (rest, f(value)))
+}
+
+// Runs the parser `f` builds from the first parser's value on the rest of the input.
+pub fn and_then<'a, P, F, Q, A, B>(parser: P, f: F) -> impl Fn(&'a str) -> ParseResult<'a, B>
+where
+    P: Parser<'a, A>,
+    F: Fn(A) -> Q,
+    Q: Parser<'a, B>,
+{
+    move |input| {
+        let (rest, value) = parser.parse(input)?;
+        f(value).parse(rest)
+    }
+}
+
+pub fn both<'a, P, Q, A, B>(first: P, second: Q) -> impl Fn(&'a str) -> ParseResult<'a, (A, B)>
+where
+    P: Parser<'a, A>,
+    Q: Parser<'a, B>,
+{
+    move |input| {
+        let (rest, a) = first.parse(input)?;
+        let (rest, b) = second.parse(rest)?;
+        Ok((rest, (a, b)))
+    }
+}
+
+pub fn left<'a, P, Q, A, B>(first: P, second: Q) -> impl Fn(&'a str) -> ParseResult<'a, A>
+where
+    P: Parser<'a, A>,
+    Q: Parser<'a, B>,
+{
+    map(both(first, second), |(a, _)| a)
+}
+
+pub fn right<'a, P, Q, A, B>(first: P, second: Q) -> impl Fn(&'a str) -> ParseResult<'a, B>
+where
+    P: Parser<'a, A>,
+    Q: Parser<'a, B>,
+{
+    map(both(first, second), |(_, b)| b)
+}
+
+// Applies `parser` as many times as it succeeds while consuming input.
+pub fn repeated<'a, P, A>(parser: P) -> impl Fn(&'a str) -> ParseResult<'a, Vec<A>>
+where
+    P: Parser<'a, A>,
+{
+    move |mut input| {
+        let mut values = Vec::new();
+        while let Ok((rest, value)) = parser.parse(input) {
+            if rest.len() == input.len() {
+                break;
+            }
+            values.push(value);
+            input = rest;
+        }
+        Ok((input, values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching::{character, literal, one_or_more, string};
+
+    fn number<'a>() -> impl Fn(&'a str) -> ParseResult<'a, usize> {
+        map(
+            recognize(one_or_more(character(|c| c.is_ascii_digit()))),
+            |digits: &str| digits.parse().unwrap(),
+        )
+    }
+
+    fn rest(input: &str) -> ParseResult<'_, &str> {
+        Ok(("", input))
+    }
+
+    #[test]
+    fn test_password_policy() {
+        let range = both(left(number(), recognize(literal('-'))), number());
+        let letter = left(
+            recognize(character(|c| c.is_ascii_lowercase())),
+            recognize(string(": ")),
+        );
+        let policy = both(both(left(range, recognize(literal(' '))), letter), rest);
+
+        assert_eq!(
+            Ok(("", (((1, 3), "a"), "abcde"))),
+            policy.parse("1-3 a: abcde")
+        );
+        assert_eq!(Err("x-3 a: abcde"), policy.parse("x-3 a: abcde"));
+    }
+
+    #[test]
+    fn test_and_then() {
+        // A count followed by that many 'a's.
+        let counted = and_then(left(number(), recognize(literal(':'))), |n| {
+            move |mut input: &'static str| {
+                for _ in 0..n {
+                    input = literal('a').matches(input)?;
+                }
+                Ok((input, n))
+            }
+        });
+
+        assert_eq!(Ok(("b", 3)), counted.parse("3:aaab"));
+        assert_eq!(Err("b"), counted.parse("3:aab"));
+    }
+
+    #[test]
+    fn test_repeated() {
+        let numbers = repeated(left(number(), recognize(string(","))));
+        assert_eq!(Ok(("4", vec![1, 22, 333])), numbers.parse("1,22,333,4"));
+        assert_eq!(Ok(("x", Vec::new())), numbers.parse("x"));
+    }
+}
@@ -1,67 +1,292 @@
 use crate::matching::*;
-use scan_fmt::scan_fmt;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::rc::{Rc, Weak};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Rule {
     Sequence(Vec<usize>),
-    Either((Vec<usize>, Vec<usize>)),
-    Literal(char),
+    Either(Vec<Vec<usize>>),
+    Literal(String),
 }
 
-pub fn parse_rules(input: &str) -> HashMap<usize, Rule> {
-    let mut rules = HashMap::new();
+impl Rule {
+    // Indices of the other rules this one refers to, in order of appearance.
+    pub fn references(&self) -> Vec<usize> {
+        match self {
+            Rule::Literal(_) => Vec::new(),
+            Rule::Sequence(numbers) => numbers.clone(),
+            Rule::Either(alternatives) => alternatives.iter().flatten().copied().collect(),
+        }
+    }
 
-    for line in input.lines() {
-        if let Ok((index, character)) = scan_fmt!(line, "{d}: \"{}\"", usize, char) {
-            rules.insert(index, Rule::Literal(character));
-        } else if let Ok((index, a, b)) =
-            scan_fmt!(line, "{d}: {[0-9 ]} | {[0-9 ]}", usize, String, String)
-        {
-            rules.insert(
-                index,
-                Rule::Either((parse_sequence(&a), parse_sequence(&b))),
-            );
-        } else if let Ok((index, numbers)) = scan_fmt!(line, "{d}: {[0-9 ]}", usize, String) {
-            rules.insert(index, Rule::Sequence(parse_sequence(&numbers)));
+    // The sequences of rules this one can expand to. Literals have none.
+    pub fn alternatives(&self) -> Vec<&[usize]> {
+        match self {
+            Rule::Literal(_) => Vec::new(),
+            Rule::Sequence(numbers) => vec![numbers],
+            Rule::Either(alternatives) => alternatives.iter().map(|a| a.as_slice()).collect(),
         }
     }
+}
 
-    rules
+// Lines are numbered from 1.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ParseError {
+    MissingColon { line: usize },
+    BadIndex { line: usize, text: String },
+    BadReference { line: usize, text: String },
+    EmptyAlternative { line: usize },
+    UnterminatedLiteral { line: usize },
+    EmptyLiteral { line: usize },
+    TrailingInput { line: usize, text: String },
+    DuplicateRule { line: usize, index: usize, previous: usize },
+    UndefinedRule { line: usize, index: usize },
 }
 
-fn parse_sequence(input: &str) -> Vec<usize> {
-    input
-        .split_whitespace()
-        .filter_map(|n| n.parse().ok())
-        .collect()
+impl ParseError {
+    pub fn line(&self) -> usize {
+        match self {
+            ParseError::MissingColon { line }
+            | ParseError::BadIndex { line, .. }
+            | ParseError::BadReference { line, .. }
+            | ParseError::EmptyAlternative { line }
+            | ParseError::UnterminatedLiteral { line }
+            | ParseError::EmptyLiteral { line }
+            | ParseError::TrailingInput { line, .. }
+            | ParseError::DuplicateRule { line, .. }
+            | ParseError::UndefinedRule { line, .. } => *line,
+        }
+    }
 }
 
-pub fn create_matcher(index: usize, rules: &HashMap<usize, Rule>) -> BoxedMatcher {
-    let rule = rules.get(&index).expect("Looking for non-existent rule");
-    match rule {
-        Rule::Literal(c) => BoxedMatcher::new(literal(*c)),
-        Rule::Sequence(numbers) => {
-            let mut matcher = create_matcher(numbers[0], rules);
-            for n in &numbers[1..] {
-                matcher = BoxedMatcher::new(pair(matcher, create_matcher(*n, rules)));
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line())?;
+        match self {
+            ParseError::MissingColon { .. } => write!(f, "expected \"<index>: <rule>\""),
+            ParseError::BadIndex { text, .. } => write!(f, "invalid rule index \"{}\"", text),
+            ParseError::BadReference { text, .. } => {
+                write!(f, "invalid rule reference \"{}\"", text)
             }
-            matcher
+            ParseError::EmptyAlternative { .. } => write!(f, "empty alternative"),
+            ParseError::UnterminatedLiteral { .. } => write!(f, "unterminated string literal"),
+            ParseError::EmptyLiteral { .. } => write!(f, "empty string literal"),
+            ParseError::TrailingInput { text, .. } => {
+                write!(f, "unexpected \"{}\" after string literal", text)
+            }
+            ParseError::DuplicateRule { index, previous, .. } => {
+                write!(f, "rule {} is already defined on line {}", index, previous)
+            }
+            ParseError::UndefinedRule { index, .. } => write!(f, "rule {} is not defined", index),
         }
-        Rule::Either((a, b)) => {
-            let mut left = create_matcher(a[0], rules);
-            for n in &a[1..] {
-                left = BoxedMatcher::new(pair(left, create_matcher(*n, rules)));
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseErrors(pub Vec<ParseError>);
+
+impl fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
             }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ParseErrors {}
+
+// One rule per line, as `<index>: "<literal>"` or `<index>: <indices> | <indices> | ...`.
+// Blank lines are skipped and `#` starts a comment outside string literals.
+pub fn parse_rules(input: &str) -> Result<HashMap<usize, Rule>, ParseErrors> {
+    let mut rules = HashMap::new();
+    extend_rules(&mut rules, input)?;
+    Ok(rules)
+}
 
-            let mut right = create_matcher(b[0], rules);
-            for n in &b[1..] {
-                right = BoxedMatcher::new(pair(right, create_matcher(*n, rules)));
+// Adds the rules in `input` to `rules`, replacing any with the same index. Every rule
+// referred to must be defined either in `input` or already in `rules`. On error `rules`
+// is left unchanged.
+pub fn extend_rules(rules: &mut HashMap<usize, Rule>, input: &str) -> Result<(), ParseErrors> {
+    let mut errors = Vec::new();
+    let mut parsed = Vec::new();
+    let mut defined_on: HashMap<usize, usize> = HashMap::new();
+    let mut unparsed_lines = false;
+
+    for (i, text) in input.lines().enumerate() {
+        let line = i + 1;
+        match parse_line(text, line) {
+            Ok(None) => {}
+            Ok(Some((index, rule))) => match defined_on.get(&index) {
+                Some(&previous) => errors.push(ParseError::DuplicateRule {
+                    line,
+                    index,
+                    previous,
+                }),
+                None => {
+                    defined_on.insert(index, line);
+                    parsed.push((line, index, rule));
+                }
+            },
+            Err(error) => {
+                errors.push(error);
+                unparsed_lines = true;
+            }
+        }
+    }
+
+    // A line that failed to parse may well define the rules that seem to be missing.
+    if !unparsed_lines {
+        for (line, _, rule) in &parsed {
+            for index in rule.references() {
+                let error = ParseError::UndefinedRule { line: *line, index };
+                let defined = defined_on.contains_key(&index) || rules.contains_key(&index);
+                if !defined && !errors.contains(&error) {
+                    errors.push(error);
+                }
             }
+        }
+    }
+
+    if !errors.is_empty() {
+        errors.sort_by_key(ParseError::line);
+        return Err(ParseErrors(errors));
+    }
+
+    rules.extend(parsed.into_iter().map(|(_, index, rule)| (index, rule)));
+    Ok(())
+}
+
+fn parse_line(text: &str, line: usize) -> Result<Option<(usize, Rule)>, ParseError> {
+    let text = strip_comment(text).trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    let colon = text.find(':').ok_or(ParseError::MissingColon { line })?;
+    let index_text = text[..colon].trim();
+    let index = index_text.parse().map_err(|_| ParseError::BadIndex {
+        line,
+        text: index_text.to_string(),
+    })?;
+
+    let body = text[colon + 1..].trim();
+    let rule = if let Some(literal) = body.strip_prefix('"') {
+        let end = literal.find('"').ok_or(ParseError::UnterminatedLiteral { line })?;
+        let trailing = literal[end + 1..].trim();
+        if end == 0 {
+            return Err(ParseError::EmptyLiteral { line });
+        }
+        if !trailing.is_empty() {
+            return Err(ParseError::TrailingInput {
+                line,
+                text: trailing.to_string(),
+            });
+        }
+        Rule::Literal(literal[..end].to_string())
+    } else {
+        let mut alternatives = body
+            .split('|')
+            .map(|alternative| parse_sequence(alternative, line))
+            .collect::<Result<Vec<_>, _>>()?;
+        if alternatives.len() == 1 {
+            Rule::Sequence(alternatives.remove(0))
+        } else {
+            Rule::Either(alternatives)
+        }
+    };
+
+    Ok(Some((index, rule)))
+}
 
-            BoxedMatcher::new(either(left, right))
+fn strip_comment(text: &str) -> &str {
+    let mut in_literal = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => in_literal = !in_literal,
+            '#' if !in_literal => return &text[..i],
+            _ => {}
         }
     }
+    text
+}
+
+fn parse_sequence(input: &str, line: usize) -> Result<Vec<usize>, ParseError> {
+    let numbers = input
+        .split_whitespace()
+        .map(|n| {
+            n.parse().map_err(|_| ParseError::BadReference {
+                line,
+                text: n.to_string(),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if numbers.is_empty() {
+        Err(ParseError::EmptyAlternative { line })
+    } else {
+        Ok(numbers)
+    }
+}
+
+pub fn create_matcher(index: usize, rules: &HashMap<usize, Rule>) -> StrMatcher<'static> {
+    let table = create_rule_table(index, rules);
+    StrMatcher::new(TableMatcher::new(index, table))
+}
+
+// Builds one matcher per rule reachable from `index`, with references between rules
+// resolved when matching. Rules that can't be reached may refer to rules that don't exist.
+pub fn create_rule_table(
+    index: usize,
+    rules: &HashMap<usize, Rule>,
+) -> Rc<RuleTable<StrMatcher<'static>>> {
+    let mut reachable = HashMap::new();
+    let mut pending = vec![index];
+    while let Some(index) = pending.pop() {
+        if let Entry::Vacant(entry) = reachable.entry(index) {
+            let rule = rules.get(&index).expect("Looking for non-existent rule");
+            pending.extend(rule.references());
+            entry.insert(rule);
+        }
+    }
+
+    RuleTable::new(|table| {
+        reachable
+            .into_iter()
+            .map(|(index, rule)| {
+                let matcher = match rule {
+                    Rule::Literal(text) => StrMatcher::new(string(text)),
+                    Rule::Sequence(numbers) => rule_sequence(numbers, table),
+                    Rule::Either(alternatives) => StrMatcher::new(choice(
+                        alternatives
+                            .iter()
+                            .map(|numbers| rule_sequence(numbers, table))
+                            .collect(),
+                    )),
+                };
+                (index, matcher)
+            })
+            .collect()
+    })
+}
+
+fn rule_sequence(
+    numbers: &[usize],
+    table: &Weak<RuleTable<StrMatcher<'static>>>,
+) -> StrMatcher<'static> {
+    let mut matcher = StrMatcher::new(rule_ref(numbers[0], table));
+    for n in &numbers[1..] {
+        matcher = StrMatcher::new(pair(matcher, rule_ref(*n, table)));
+    }
+    matcher
 }
 
 #[cfg(test)]
@@ -77,16 +302,229 @@ mod tests {
         3: 4 5 | 5 4
         4: \"a\"
         5: \"b\"",
-        );
+        ).unwrap();
 
         let matcher = create_matcher(0, &rules);
 
         assert!(matcher.matches("ababbb").is_ok());
-        assert_eq!(true, matcher.exactly_matches("ababbb"));
+        assert!(matcher.exactly_matches("ababbb"));
         assert!(matcher.matches("abbbab").is_ok());
         assert!(matcher.matches("bababa").is_err());
         assert!(matcher.matches("aaabbb").is_err());
         assert_eq!(Ok("b"), matcher.matches("aaaabbb"));
-        assert_eq!(false, matcher.exactly_matches("aaaabbb"));
+        assert!(!matcher.exactly_matches("aaaabbb"));
+    }
+
+    const LOOPING_RULES: &str = "42: 9 14 | 10 1
+        9: 14 27 | 1 26
+        10: 23 14 | 28 1
+        1: \"a\"
+        11: 42 31
+        5: 1 14 | 15 1
+        19: 14 1 | 14 14
+        12: 24 14 | 19 1
+        16: 15 1 | 14 14
+        31: 14 17 | 1 13
+        6: 14 14 | 1 14
+        2: 1 24 | 14 4
+        0: 8 11
+        13: 14 3 | 1 12
+        15: 1 | 14
+        17: 14 2 | 1 7
+        23: 25 1 | 22 14
+        28: 16 1
+        4: 1 1
+        20: 14 14 | 1 15
+        3: 5 14 | 16 1
+        27: 1 6 | 14 18
+        14: \"b\"
+        21: 14 1 | 1 14
+        25: 1 1 | 1 14
+        22: 14 14
+        8: 42
+        26: 14 22 | 1 20
+        18: 15 15
+        7: 14 5 | 1 21
+        24: 14 1";
+
+    const LOOPING_MESSAGES: &str = "abbbbbabbbaaaababbaabbbbabababbbabbbbbbabaaaa
+        bbabbbbaabaabba
+        babbbbaabbbbbabbbbbbaabaaabaaa
+        aaabbbbbbaaaabaababaabababbabaaabbababababaaa
+        bbbbbbbaaaabbbbaaabbabaaa
+        bbbababbbbaaaaaaaabbababaaababaabab
+        ababaaaaaabaaab
+        ababaaaaabbbaba
+        baabbaaaabbaaaababbaababb
+        abbbbabbbbaaaababbbbbbaaaababb
+        aaaaabbaabaaaaababaa
+        aaaabbaaaabbaaa
+        aaaabbaabbaaaaaaabbbabbbaaabbaabaaa
+        babaaabbbaaabaababbaabababaaab
+        aabbbbbaabbbaaaaaabbbbbababaaaaabbaaabba";
+
+    fn count_matches(rules: &HashMap<usize, Rule>) -> usize {
+        let matcher = create_matcher(0, rules);
+        LOOPING_MESSAGES
+            .lines()
+            .filter(|message| matcher.exactly_matches(message.trim()))
+            .count()
+    }
+
+    #[test]
+    fn part2_example_without_loops() {
+        let rules = parse_rules(LOOPING_RULES).unwrap();
+        assert_eq!(3, count_matches(&rules));
+    }
+
+    #[test]
+    fn part2_example_with_loops() {
+        let mut rules = parse_rules(LOOPING_RULES).unwrap();
+        extend_rules(&mut rules, "8: 42 | 42 8\n11: 42 31 | 42 11 31").unwrap();
+
+        assert_eq!(12, count_matches(&rules));
+        assert!(create_matcher(0, &rules).exactly_matches("babbbbaabbbbbabbbbbbaabaaabaaa"));
+    }
+
+    #[test]
+    fn mutually_recursive_rules() {
+        let rules = parse_rules(
+            "0: 1 2
+        1: 3 | 3 2 1
+        2: 4
+        3: \"a\"
+        4: \"b\"",
+        ).unwrap();
+
+        let matcher = create_matcher(0, &rules);
+        assert!(matcher.exactly_matches("ab"));
+        assert!(matcher.exactly_matches("ababab"));
+        assert!(!matcher.exactly_matches("abba"));
+    }
+
+    #[test]
+    fn left_recursion_stops_instead_of_overflowing() {
+        // Only the alternative that doesn't recurse on the left can match; `Earley`
+        // matches the whole language.
+        let rules = parse_rules("0: 0 1 | 1\n1: \"a\"").unwrap();
+        let matcher = create_matcher(0, &rules);
+        assert!(matcher.exactly_matches("a"));
+        assert!(!matcher.exactly_matches("aaa"));
+        assert_eq!(Ok("aa"), matcher.matches("aaa"));
+        assert!(create_rule_table(0, &rules).contains(0));
+
+        // The guard is only for the same point of the input, so right recursion still works.
+        let rules = parse_rules("0: 1 0 | 1\n1: \"a\"").unwrap();
+        assert!(create_matcher(0, &rules).exactly_matches("aaa"));
+    }
+
+    #[test]
+    fn unreachable_rules_may_be_incomplete() {
+        let rules = HashMap::from([
+            (0, Rule::Sequence(vec![1, 1])),
+            (1, Rule::Literal("a".to_string())),
+            (2, Rule::Sequence(vec![1, 3])),
+        ]);
+        assert!(create_matcher(0, &rules).exactly_matches("aa"));
+        assert!(!create_rule_table(0, &rules).contains(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Looking for non-existent rule")]
+    fn missing_rule_is_reported_when_building() {
+        let mut rules = HashMap::new();
+        rules.insert(0, Rule::Sequence(vec![1, 2]));
+        rules.insert(1, Rule::Literal("a".to_string()));
+        let _ = create_matcher(0, &rules);
+    }
+
+    #[test]
+    fn extended_syntax() {
+        let rules = parse_rules(
+            "# Greetings
+        0: 1 | 2 | 3 2  # three ways
+
+        1: \"hello\"
+        2: \"a#b\"
+        3: 1 1",
+        )
+        .unwrap();
+
+        assert_eq!(Rule::Either(vec![vec![1], vec![2], vec![3, 2]]), rules[&0]);
+        assert_eq!(Rule::Literal("a#b".to_string()), rules[&2]);
+
+        let matcher = create_matcher(0, &rules);
+        assert!(matcher.exactly_matches("hello"));
+        assert!(matcher.exactly_matches("a#b"));
+        assert!(matcher.exactly_matches("hellohelloa#b"));
+        assert!(!matcher.exactly_matches("helloa#b"));
+    }
+
+    #[test]
+    fn reports_every_bad_line() {
+        let errors = parse_rules(
+            "0: 1 2
+        1: \"a
+        two: 1
+        2: 1 | | 3
+        3 \"b\"
+        4: 1 x
+        5: \"\"
+        6: \"a\" 1",
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            ParseErrors(vec![
+                ParseError::UnterminatedLiteral { line: 2 },
+                ParseError::BadIndex {
+                    line: 3,
+                    text: "two".to_string()
+                },
+                ParseError::EmptyAlternative { line: 4 },
+                ParseError::MissingColon { line: 5 },
+                ParseError::BadReference {
+                    line: 6,
+                    text: "x".to_string()
+                },
+                ParseError::EmptyLiteral { line: 7 },
+                ParseError::TrailingInput {
+                    line: 8,
+                    text: "1".to_string()
+                },
+            ]),
+            errors
+        );
+        assert_eq!("line 6: invalid rule reference \"x\"", errors.0[4].to_string());
+    }
+
+    #[test]
+    fn reports_undefined_and_duplicate_rules() {
+        let errors = parse_rules("0: 1 7 7\n1: \"a\"\n1: \"b\"").unwrap_err();
+        assert_eq!(
+            ParseErrors(vec![
+                ParseError::UndefinedRule { line: 1, index: 7 },
+                ParseError::DuplicateRule {
+                    line: 3,
+                    index: 1,
+                    previous: 2
+                },
+            ]),
+            errors
+        );
+        assert_eq!(
+            "line 1: rule 7 is not defined\nline 3: rule 1 is already defined on line 2",
+            errors.to_string()
+        );
+    }
+
+    #[test]
+    fn extensions_may_refer_to_existing_rules() {
+        let mut rules = parse_rules("0: 1\n1: \"a\"").unwrap();
+        assert!(extend_rules(&mut rules, "0: 2").is_err());
+        assert_eq!(Rule::Sequence(vec![1]), rules[&0]);
+
+        extend_rules(&mut rules, "0: 1 2\n2: 1 | 1 2").unwrap();
+        assert!(create_matcher(0, &rules).exactly_matches("aaa"));
     }
 }
@@ -0,0 +1,435 @@
+use crate::matching::{MatchResult, Matcher, StrMatcher};
+use crate::parse_tree::{Diagnostics, Parse};
+use crate::parsing::{create_matcher, Rule};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+// True if a cycle of rules can be reached from `index`. Such grammars may not be regular,
+// so they are left to the combinator matcher.
+pub fn is_recursive(index: usize, rules: &HashMap<usize, Rule>) -> bool {
+    fn visit(
+        index: usize,
+        rules: &HashMap<usize, Rule>,
+        on_stack: &mut HashSet<usize>,
+        done: &mut HashSet<usize>,
+    ) -> bool {
+        if done.contains(&index) {
+            return false;
+        }
+        if !on_stack.insert(index) {
+            return true;
+        }
+
+        let rule = rules.get(&index).expect("Looking for non-existent rule");
+        let cyclic = rule
+            .references()
+            .into_iter()
+            .any(|n| visit(n, rules, on_stack, done));
+
+        on_stack.remove(&index);
+        done.insert(index);
+        cyclic
+    }
+
+    visit(index, rules, &mut HashSet::new(), &mut HashSet::new())
+}
+
+// The characters with a special meaning in a regular expression, which must be escaped.
+fn is_meta(c: char) -> bool {
+    matches!(
+        c,
+        '\\' | '.' | '+' | '*' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$' | '#'
+            | '&' | '-' | '~'
+    )
+}
+
+// The language of rule `index` as a regular expression, or `None` if the rules are recursive.
+pub fn to_pattern(index: usize, rules: &HashMap<usize, Rule>) -> Option<String> {
+    fn pattern(index: usize, rules: &HashMap<usize, Rule>, memo: &mut HashMap<usize, String>) -> String {
+        if let Some(p) = memo.get(&index) {
+            return p.clone();
+        }
+
+        let sequence = |numbers: &[usize], memo: &mut HashMap<usize, String>| -> String {
+            numbers.iter().map(|&n| pattern(n, rules, memo)).collect()
+        };
+
+        let p = match rules.get(&index).expect("Looking for non-existent rule") {
+            Rule::Literal(text) => text
+                .chars()
+                .map(|c| if is_meta(c) { format!("\\{}", c) } else { c.to_string() })
+                .collect(),
+            Rule::Sequence(numbers) => sequence(numbers, memo),
+            Rule::Either(alternatives) => {
+                let alternatives: Vec<String> =
+                    alternatives.iter().map(|a| sequence(a, memo)).collect();
+                format!("(?:{})", alternatives.join("|"))
+            }
+        };
+
+        memo.insert(index, p.clone());
+        p
+    }
+
+    if is_recursive(index, rules) {
+        None
+    } else {
+        Some(pattern(index, rules, &mut HashMap::new()))
+    }
+}
+
+// Thompson construction: each state has a list of transitions, `None` meaning epsilon.
+#[derive(Default)]
+struct Nfa {
+    transitions: Vec<Vec<(Option<char>, usize)>>,
+}
+
+impl Nfa {
+    fn add_state(&mut self) -> usize {
+        self.transitions.push(Vec::new());
+        self.transitions.len() - 1
+    }
+
+    // Adds rule `index` starting at `start` and returns the state reached after it.
+    fn add_rule(&mut self, index: usize, start: usize, rules: &HashMap<usize, Rule>) -> usize {
+        match &rules[&index] {
+            Rule::Literal(text) => text.chars().fold(start, |state, c| {
+                let next = self.add_state();
+                self.transitions[state].push((Some(c), next));
+                next
+            }),
+            Rule::Sequence(numbers) => self.add_sequence(numbers, start, rules),
+            Rule::Either(alternatives) => {
+                let end = self.add_state();
+                for branch in alternatives {
+                    let branch_end = self.add_sequence(branch, start, rules);
+                    self.transitions[branch_end].push((None, end));
+                }
+                end
+            }
+        }
+    }
+
+    fn add_sequence(&mut self, numbers: &[usize], start: usize, rules: &HashMap<usize, Rule>) -> usize {
+        // Each alternative needs its own entry state so branches can't leak into each other.
+        let entry = self.add_state();
+        self.transitions[start].push((None, entry));
+        numbers
+            .iter()
+            .fold(entry, |state, &n| self.add_rule(n, state, rules))
+    }
+
+    fn closure(&self, states: impl IntoIterator<Item = usize>) -> BTreeSet<usize> {
+        let mut closure = BTreeSet::new();
+        let mut pending: Vec<usize> = states.into_iter().collect();
+
+        while let Some(state) = pending.pop() {
+            if closure.insert(state) {
+                pending.extend(
+                    self.transitions[state]
+                        .iter()
+                        .filter(|(c, _)| c.is_none())
+                        .map(|&(_, target)| target),
+                );
+            }
+        }
+
+        closure
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Dfa {
+    start: usize,
+    transitions: Vec<HashMap<char, usize>>,
+    accepting: Vec<bool>,
+}
+
+impl Dfa {
+    // Builds a minimal DFA for rule `index`, or returns `None` if the rules are recursive.
+    pub fn compile(index: usize, rules: &HashMap<usize, Rule>) -> Option<Dfa> {
+        if is_recursive(index, rules) {
+            return None;
+        }
+
+        let mut nfa = Nfa::default();
+        let start = nfa.add_state();
+        let end = nfa.add_rule(index, start, rules);
+
+        Some(Dfa::from_nfa(&nfa, start, end).minimise())
+    }
+
+    // Subset construction.
+    fn from_nfa(nfa: &Nfa, start: usize, end: usize) -> Dfa {
+        let initial = nfa.closure(vec![start]);
+        let mut ids = HashMap::new();
+        let mut subsets = vec![initial.clone()];
+        ids.insert(initial, 0);
+
+        let mut transitions = Vec::new();
+        let mut next = 0;
+        while next < subsets.len() {
+            let mut moves: HashMap<char, Vec<usize>> = HashMap::new();
+            for &state in &subsets[next] {
+                for &(c, target) in &nfa.transitions[state] {
+                    if let Some(c) = c {
+                        moves.entry(c).or_default().push(target);
+                    }
+                }
+            }
+
+            let mut row = HashMap::new();
+            for (c, targets) in moves {
+                let subset = nfa.closure(targets);
+                let id = *ids.entry(subset.clone()).or_insert_with(|| {
+                    subsets.push(subset);
+                    subsets.len() - 1
+                });
+                row.insert(c, id);
+            }
+            transitions.push(row);
+            next += 1;
+        }
+
+        let accepting = subsets.iter().map(|subset| subset.contains(&end)).collect();
+
+        Dfa {
+            start: 0,
+            transitions,
+            accepting,
+        }
+    }
+
+    // Moore's algorithm: split states by acceptance, then repeatedly by which class each
+    // character leads to, until no class splits further. Missing transitions act as a dead state.
+    fn minimise(&self) -> Dfa {
+        let mut alphabet: Vec<char> = self
+            .transitions
+            .iter()
+            .flat_map(|row| row.keys().copied())
+            .collect();
+        alphabet.sort_unstable();
+        alphabet.dedup();
+
+        let mut class: Vec<usize> = self.accepting.iter().map(|&a| a as usize).collect();
+        let mut class_count = 0;
+
+        loop {
+            let mut signatures = HashMap::new();
+            let refined: Vec<usize> = (0..self.transitions.len())
+                .map(|state| {
+                    let signature: (usize, Vec<Option<usize>>) = (
+                        class[state],
+                        alphabet
+                            .iter()
+                            .map(|c| self.transitions[state].get(c).map(|&t| class[t]))
+                            .collect(),
+                    );
+                    let next_id = signatures.len();
+                    *signatures.entry(signature).or_insert(next_id)
+                })
+                .collect();
+
+            let count = signatures.len();
+            class = refined;
+            if count == class_count {
+                break;
+            }
+            class_count = count;
+        }
+
+        let mut transitions = vec![HashMap::new(); class_count];
+        let mut accepting = vec![false; class_count];
+        for (state, row) in self.transitions.iter().enumerate() {
+            accepting[class[state]] = self.accepting[state];
+            for (&c, &target) in row {
+                transitions[class[state]].insert(c, class[target]);
+            }
+        }
+
+        Dfa {
+            start: class[self.start],
+            transitions,
+            accepting,
+        }
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.transitions.len()
+    }
+}
+
+// Remainders are listed shortest match first, since the DFA reads the input only once.
+impl<'a> Matcher<&'a str> for Dfa {
+    fn matches(&self, input: &'a str) -> MatchResult<&'a str> {
+        self.remainders(input).into_iter().next().ok_or(input)
+    }
+
+    fn remainders(&self, input: &'a str) -> Vec<&'a str> {
+        let mut remainders = Vec::new();
+        let mut state = self.start;
+
+        if self.accepting[state] {
+            remainders.push(input);
+        }
+
+        for (offset, c) in input.char_indices() {
+            match self.transitions[state].get(&c) {
+                Some(&next) => state = next,
+                None => break,
+            }
+            if self.accepting[state] {
+                remainders.push(&input[offset + c.len_utf8()..]);
+            }
+        }
+
+        remainders
+    }
+
+    fn exactly_matches(&self, input: &'a str) -> bool {
+        let mut state = self.start;
+        for c in input.chars() {
+            match self.transitions[state].get(&c) {
+                Some(&next) => state = next,
+                None => return false,
+            }
+        }
+        self.accepting[state]
+    }
+}
+
+// Uses a DFA when the rules allow it and falls back to the combinator matcher otherwise.
+// Either way, remainders are listed shortest match first, as the DFA finds them, so that
+// `matches` gives the same result whichever matcher is used.
+pub enum CompiledMatcher {
+    Dfa(Dfa),
+    Combinator(StrMatcher<'static>),
+}
+
+pub fn compile_matcher(index: usize, rules: &HashMap<usize, Rule>) -> CompiledMatcher {
+    match Dfa::compile(index, rules) {
+        Some(dfa) => CompiledMatcher::Dfa(dfa),
+        None => CompiledMatcher::Combinator(create_matcher(index, rules)),
+    }
+}
+
+impl<'a> Matcher<&'a str> for CompiledMatcher {
+    fn matches(&self, input: &'a str) -> MatchResult<&'a str> {
+        match self {
+            CompiledMatcher::Dfa(dfa) => dfa.matches(input),
+            CompiledMatcher::Combinator(_) => {
+                self.remainders(input).into_iter().next().ok_or(input)
+            }
+        }
+    }
+
+    fn remainders(&self, input: &'a str) -> Vec<&'a str> {
+        match self {
+            CompiledMatcher::Dfa(dfa) => dfa.remainders(input),
+            CompiledMatcher::Combinator(matcher) => {
+                let mut remainders = matcher.remainders(input);
+                remainders.sort_by_key(|remaining| std::cmp::Reverse(remaining.len()));
+                remainders
+            }
+        }
+    }
+
+    fn exactly_matches(&self, input: &'a str) -> bool {
+        match self {
+            CompiledMatcher::Dfa(dfa) => dfa.exactly_matches(input),
+            CompiledMatcher::Combinator(matcher) => matcher.exactly_matches(input),
+        }
+    }
+
+    // The DFA has no notion of rules, so it yields no trees.
+    fn parses(&self, input: &'a str, diagnostics: &mut Diagnostics) -> Vec<Parse<&'a str>> {
+        match self {
+            CompiledMatcher::Dfa(dfa) => dfa.parses(input, diagnostics),
+            CompiledMatcher::Combinator(matcher) => matcher.parses(input, diagnostics),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::{extend_rules, parse_rules};
+
+    const EXAMPLE: &str = "0: 4 1 5
+        1: 2 3 | 3 2
+        2: 4 4 | 5 5
+        3: 4 5 | 5 4
+        4: \"a\"
+        5: \"b\"";
+
+    #[test]
+    fn pattern_for_example() {
+        let rules = parse_rules(EXAMPLE).unwrap();
+        assert_eq!(
+            Some("a(?:(?:aa|bb)(?:ab|ba)|(?:ab|ba)(?:aa|bb))b".to_string()),
+            to_pattern(0, &rules)
+        );
+    }
+
+    #[test]
+    fn pattern_escapes_only_metacharacters() {
+        let rules = HashMap::from([
+            (0, Rule::Sequence(vec![1, 2])),
+            (1, Rule::Literal("é a-b".to_string())),
+            (2, Rule::Literal("(x|y)*".to_string())),
+        ]);
+        assert_eq!(Some("é a\\-b\\(x\\|y\\)\\*".to_string()), to_pattern(0, &rules));
+    }
+
+    #[test]
+    fn dfa_matches_example() {
+        let rules = parse_rules(EXAMPLE).unwrap();
+        let dfa = Dfa::compile(0, &rules).unwrap();
+
+        assert!(dfa.exactly_matches("ababbb"));
+        assert!(dfa.exactly_matches("abbbab"));
+        assert!(!dfa.exactly_matches("bababa"));
+        assert!(!dfa.exactly_matches("aaabbb"));
+        assert!(!dfa.exactly_matches("aaaabbb"));
+        assert_eq!(Ok("b"), dfa.matches("aaaabbb"));
+        assert_eq!(10, dfa.state_count());
+    }
+
+    #[test]
+    fn dfa_agrees_with_combinators_on_puzzle_input() {
+        let rules = parse_rules(include_str!("rules.txt")).unwrap();
+        let dfa = Dfa::compile(0, &rules).unwrap();
+        let matcher = create_matcher(0, &rules);
+
+        for message in include_str!("messages.txt").lines() {
+            assert_eq!(matcher.exactly_matches(message), dfa.exactly_matches(message), "{}", message);
+        }
+    }
+
+    #[test]
+    fn recursive_rules_fall_back_to_combinators() {
+        let mut rules = parse_rules(EXAMPLE).unwrap();
+        extend_rules(&mut rules, "0: 4 6 5\n6: 1 | 1 6").unwrap();
+
+        assert!(is_recursive(0, &rules));
+        assert_eq!(None, to_pattern(0, &rules));
+
+        let matcher = compile_matcher(0, &rules);
+        assert!(matches!(matcher, CompiledMatcher::Combinator(_)));
+        assert!(matcher.exactly_matches("ababbb"));
+        assert!(matcher.exactly_matches("aaaababbbb"));
+    }
+
+    #[test]
+    fn variants_agree_on_order() {
+        // Rule 1 tries the longer alternative first, which the DFA can't know about.
+        let rules = parse_rules("0: 1\n1: 2 2 | 2\n2: \"a\"").unwrap();
+        let dfa = compile_matcher(0, &rules);
+        let combinator = CompiledMatcher::Combinator(create_matcher(0, &rules));
+        assert!(matches!(dfa, CompiledMatcher::Dfa(_)));
+
+        assert_eq!(vec!["a", ""], dfa.remainders("aa"));
+        assert_eq!(dfa.remainders("aa"), combinator.remainders("aa"));
+        assert_eq!(Ok("a"), dfa.matches("aa"));
+        assert_eq!(dfa.matches("aa"), combinator.matches("aa"));
+    }
+}
@@ -0,0 +1,63 @@
+use day8::compiled::CompiledProgram;
+use day8::optimise::optimise;
+use day8::{parse_program, run, Instruction};
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 20;
+
+// Times the part 2 repair search, which runs every single-instruction mutation of the
+// puzzle input, using each interpreter in turn.
+fn main() {
+    let program = parse_program(include_str!("../src/puzzle_input.txt")).unwrap();
+    let mutations: Vec<Vec<Instruction>> = (0..program.len())
+        .filter_map(|index| {
+            let mut mutated = program.clone();
+            mutated[index] = match program[index] {
+                Instruction::Nop(n) => Instruction::Jump(n),
+                Instruction::Jump(n) => Instruction::Nop(n),
+                Instruction::Accumulator(_) => return None,
+            };
+            Some(mutated)
+        })
+        .collect();
+
+    let baseline = time("run", || mutations.iter().map(|p| checksum(run(p))).sum());
+
+    let compiled: Vec<CompiledProgram> = mutations.iter().map(|p| CompiledProgram::new(p)).collect();
+    let fast = time("compiled", || compiled.iter().map(|p| checksum(p.run())).sum());
+
+    let optimised: Vec<CompiledProgram> = mutations
+        .iter()
+        .map(|p| CompiledProgram::new(&optimise(p)))
+        .collect();
+    let fastest = time("optimised + compiled", || {
+        optimised.iter().map(|p| checksum(p.run())).sum()
+    });
+
+    println!(
+        "speedup: compiled {:.1}x, optimised + compiled {:.1}x",
+        baseline.as_secs_f64() / fast.as_secs_f64(),
+        baseline.as_secs_f64() / fastest.as_secs_f64()
+    );
+}
+
+fn checksum(result: Result<isize, isize>) -> isize {
+    match result {
+        Ok(acc) => acc,
+        Err(acc) => -acc,
+    }
+}
+
+fn time<F>(name: &str, f: F) -> Duration
+where
+    F: Fn() -> isize,
+{
+    let expected = f();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        assert_eq!(f(), expected);
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    println!("{:<22} {:>10.3?} per repair search", name, elapsed);
+    elapsed
+}
@@ -0,0 +1,275 @@
+use crate::Instruction;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+// Layout: magic (4 bytes), version (1 byte), FNV-1a checksum of the body (4 bytes, little endian),
+// then the body: instruction count as a varint followed by an opcode byte and a
+// zigzag-encoded varint argument for each instruction.
+pub const MAGIC: [u8; 4] = *b"AoC8";
+pub const VERSION: u8 = 1;
+const HEADER_LEN: usize = 9;
+
+const OP_NOP: u8 = 0;
+const OP_ACC: u8 = 1;
+const OP_JMP: u8 = 2;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DecodeError {
+    Truncated,
+    BadMagic,
+    UnsupportedVersion(u8),
+    ChecksumMismatch { expected: u32, actual: u32 },
+    UnknownOpcode { offset: usize, opcode: u8 },
+    ArgumentOverflow { offset: usize },
+    TrailingBytes { offset: usize },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "Bytecode is truncated"),
+            DecodeError::BadMagic => write!(f, "Not day8 bytecode: bad magic number"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "Unsupported bytecode version {}", v),
+            DecodeError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Checksum mismatch: header says {:08x}, body is {:08x}",
+                expected, actual
+            ),
+            DecodeError::UnknownOpcode { offset, opcode } => {
+                write!(f, "Unknown opcode {} at byte {}", opcode, offset)
+            }
+            DecodeError::ArgumentOverflow { offset } => {
+                write!(f, "Argument at byte {} does not fit in an isize", offset)
+            }
+            DecodeError::TrailingBytes { offset } => {
+                write!(f, "Unexpected data after the last instruction at byte {}", offset)
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+pub fn encode(program: &[Instruction]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(program.len() * 2 + 2);
+    write_varint(&mut body, program.len() as u64);
+
+    for instruction in program {
+        let (opcode, argument) = match *instruction {
+            Instruction::Nop(n) => (OP_NOP, n),
+            Instruction::Accumulator(a) => (OP_ACC, a),
+            Instruction::Jump(j) => (OP_JMP, j),
+        };
+        body.push(opcode);
+        write_varint(&mut body, zigzag(argument as i64));
+    }
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + body.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(VERSION);
+    bytes.extend_from_slice(&checksum(&body).to_le_bytes());
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<Instruction>, DecodeError> {
+    if bytes.len() < HEADER_LEN {
+        return Err(DecodeError::Truncated);
+    }
+
+    if bytes[..4] != MAGIC {
+        return Err(DecodeError::BadMagic);
+    }
+
+    if bytes[4] != VERSION {
+        return Err(DecodeError::UnsupportedVersion(bytes[4]));
+    }
+
+    let expected = u32::from_le_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]);
+    let actual = checksum(&bytes[HEADER_LEN..]);
+    if expected != actual {
+        return Err(DecodeError::ChecksumMismatch { expected, actual });
+    }
+
+    let mut offset = HEADER_LEN;
+    let count = read_varint(bytes, &mut offset)?;
+    // Every instruction takes at least two bytes, which bounds a sensible allocation.
+    let mut program = Vec::with_capacity((count as usize).min(bytes.len() / 2));
+
+    for _ in 0..count {
+        let opcode_offset = offset;
+        let opcode = *bytes.get(offset).ok_or(DecodeError::Truncated)?;
+        offset += 1;
+
+        let argument_offset = offset;
+        let argument = unzigzag(read_varint(bytes, &mut offset)?);
+        let argument: isize = argument
+            .try_into()
+            .map_err(|_| DecodeError::ArgumentOverflow {
+                offset: argument_offset,
+            })?;
+
+        program.push(match opcode {
+            OP_NOP => Instruction::Nop(argument),
+            OP_ACC => Instruction::Accumulator(argument),
+            OP_JMP => Instruction::Jump(argument),
+            opcode => {
+                return Err(DecodeError::UnknownOpcode {
+                    offset: opcode_offset,
+                    opcode,
+                })
+            }
+        });
+    }
+
+    if offset != bytes.len() {
+        return Err(DecodeError::TrailingBytes { offset });
+    }
+
+    Ok(program)
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    ((n >> 1) as i64) ^ -((n & 1) as i64)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], offset: &mut usize) -> Result<u64, DecodeError> {
+    let start = *offset;
+    let mut value = 0u64;
+    let mut shift = 0;
+
+    loop {
+        let byte = *bytes.get(*offset).ok_or(DecodeError::Truncated)?;
+        *offset += 1;
+
+        if shift == 63 && byte > 1 {
+            return Err(DecodeError::ArgumentOverflow { offset: start });
+        }
+
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+
+        shift += 7;
+        if shift > 63 {
+            return Err(DecodeError::ArgumentOverflow { offset: start });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuzz::Rng;
+
+    // Unlike `fuzz::generate`, arguments here cover the full range of `isize`.
+    fn random_program(rng: &mut Rng) -> Vec<Instruction> {
+        let len = rng.below(64);
+        (0..len)
+            .map(|_| {
+                let argument = match rng.below(4) {
+                    0 => rng.next_u64() as isize,
+                    _ => rng.between(-1000, 1000),
+                };
+                match rng.below(3) {
+                    0 => Instruction::Nop(argument),
+                    1 => Instruction::Accumulator(argument),
+                    _ => Instruction::Jump(argument),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn encodes_header_and_compact_body() {
+        let bytes = encode(&[Instruction::Accumulator(-1), Instruction::Jump(64)]);
+        assert_eq!(&bytes[..4], b"AoC8");
+        assert_eq!(bytes[4], VERSION);
+        assert_eq!(&bytes[HEADER_LEN..], &[2, OP_ACC, 1, OP_JMP, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn round_trip_puzzle_input() {
+        let program: Vec<Instruction> = include_str!("puzzle_input.txt")
+            .lines()
+            .map(|line| line.parse().unwrap())
+            .collect();
+
+        assert_eq!(decode(&encode(&program)), Ok(program));
+    }
+
+    #[test]
+    fn round_trip_random_programs_against_text_format() {
+        let mut rng = Rng::new(0);
+
+        for _ in 0..500 {
+            let program = random_program(&mut rng);
+            let text: Vec<String> = program.iter().map(|i| i.to_string()).collect();
+
+            let decoded = decode(&encode(&program)).unwrap();
+            assert_eq!(decoded, program);
+
+            let reparsed: Vec<Instruction> = text.iter().map(|line| line.parse().unwrap()).collect();
+            assert_eq!(reparsed, decoded);
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_input() {
+        let bytes = encode(&[Instruction::Nop(0), Instruction::Accumulator(5)]);
+
+        assert_eq!(decode(&bytes[..3]), Err(DecodeError::Truncated));
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(decode(&bad_magic), Err(DecodeError::BadMagic));
+
+        let mut bad_version = bytes.clone();
+        bad_version[4] = 99;
+        assert_eq!(decode(&bad_version), Err(DecodeError::UnsupportedVersion(99)));
+
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert!(matches!(decode(&flipped), Err(DecodeError::ChecksumMismatch { .. })));
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(matches!(decode(truncated), Err(DecodeError::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    fn rejects_unknown_opcode() {
+        let body = [1, 7, 0];
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&checksum(&body).to_le_bytes());
+        bytes.extend_from_slice(&body);
+
+        assert_eq!(
+            decode(&bytes),
+            Err(DecodeError::UnknownOpcode {
+                offset: HEADER_LEN + 1,
+                opcode: 7
+            })
+        );
+    }
+}
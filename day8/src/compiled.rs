@@ -0,0 +1,85 @@
+use crate::Instruction;
+
+type Operation = Box<dyn Fn(&mut isize) -> usize>;
+
+// Each instruction is compiled ahead of time into a closure which updates the accumulator
+// and returns the next program counter, with jump targets already resolved. Loop detection
+// uses a bit per instruction rather than a `HashSet`.
+pub struct CompiledProgram {
+    operations: Vec<Operation>,
+}
+
+impl CompiledProgram {
+    pub fn new(program: &[Instruction]) -> Self {
+        let operations = program
+            .iter()
+            .enumerate()
+            .map(|(pc, &instruction)| -> Operation {
+                match instruction {
+                    Instruction::Accumulator(a) => Box::new(move |acc: &mut isize| {
+                        *acc += a;
+                        pc + 1
+                    }),
+                    Instruction::Jump(j) => {
+                        let target = (pc as isize).wrapping_add(j) as usize;
+                        Box::new(move |_: &mut isize| target)
+                    }
+                    Instruction::Nop(_) => Box::new(move |_: &mut isize| pc + 1),
+                }
+            })
+            .collect();
+
+        CompiledProgram { operations }
+    }
+
+    pub fn run(&self) -> Result<isize, isize> {
+        let mut accumulator = 0;
+        let mut pointer = 0;
+        let mut visited = vec![false; self.operations.len()];
+
+        while let Some(operation) = self.operations.get(pointer) {
+            pointer = operation(&mut accumulator);
+
+            if let Some(seen) = visited.get_mut(pointer) {
+                if *seen {
+                    return Err(accumulator);
+                }
+                *seen = true;
+            }
+        }
+
+        Ok(accumulator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimise::optimise;
+    use crate::{parse_program, run};
+
+    #[test]
+    fn matches_run_on_puzzle_input_repairs() {
+        let program = parse_program(include_str!("puzzle_input.txt")).unwrap();
+
+        for index in 0..program.len() {
+            let mut mutated = program.clone();
+            mutated[index] = match program[index] {
+                Instruction::Nop(n) => Instruction::Jump(n),
+                Instruction::Jump(n) => Instruction::Nop(n),
+                Instruction::Accumulator(n) => Instruction::Accumulator(n),
+            };
+
+            let expected = run(&mutated);
+            assert_eq!(CompiledProgram::new(&mutated).run(), expected);
+            assert_eq!(CompiledProgram::new(&optimise(&mutated)).run(), expected);
+        }
+    }
+
+    #[test]
+    fn jumps_before_start_terminate() {
+        let program = parse_program("acc +4\njmp -5").unwrap();
+        assert_eq!(CompiledProgram::new(&program).run(), Ok(4));
+        assert_eq!(run(&program), Ok(4));
+    }
+}
@@ -0,0 +1,199 @@
+use crate::reference;
+use crate::Instruction;
+
+pub use rng::Rng;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Shape {
+    Any,
+    Terminating,
+    Looping,
+}
+
+pub fn generate(rng: &mut Rng, len: usize, shape: Shape) -> Vec<Instruction> {
+    for _ in 0..16 {
+        let program = random_program(rng, len);
+        if has_shape(&program, shape) {
+            return program;
+        }
+    }
+
+    let mut program = random_program(rng, len);
+    match shape {
+        Shape::Any => {}
+        // Without any jumps control can only fall off the end.
+        Shape::Terminating => {
+            for instruction in program.iter_mut() {
+                if let Instruction::Jump(j) = *instruction {
+                    *instruction = Instruction::Nop(j);
+                }
+            }
+        }
+        // Keep every jump inside the program and make the last instruction jump to itself,
+        // so control can never leave.
+        Shape::Looping => {
+            let len = len.max(1) as isize;
+            program.resize(len as usize, Instruction::Nop(0));
+            for (pc, instruction) in program.iter_mut().enumerate() {
+                if let Instruction::Jump(j) = *instruction {
+                    let target = pc as isize + j;
+                    if target < 0 || target >= len {
+                        *instruction = Instruction::Nop(j);
+                    }
+                }
+            }
+            program[len as usize - 1] = Instruction::Jump(0);
+        }
+    }
+
+    program
+}
+
+fn has_shape(program: &[Instruction], shape: Shape) -> bool {
+    match shape {
+        Shape::Any => true,
+        Shape::Terminating => reference::execute(program).is_ok(),
+        Shape::Looping => reference::execute(program).is_err(),
+    }
+}
+
+fn random_program(rng: &mut Rng, len: usize) -> Vec<Instruction> {
+    let reach = len as isize + 1;
+    (0..len)
+        .map(|_| match rng.below(10) {
+            0..=3 => Instruction::Accumulator(rng.between(-50, 50)),
+            4..=6 => Instruction::Jump(rng.between(-reach, reach)),
+            _ => Instruction::Nop(rng.between(-reach, reach)),
+        })
+        .collect()
+}
+
+// Greedily applies the first simplification that still fails until none do. Every candidate
+// is strictly smaller (shorter, smaller arguments, or fewer non-`nop` instructions), so this
+// always finishes.
+pub fn shrink<F>(program: &[Instruction], fails: F) -> Vec<Instruction>
+where
+    F: Fn(&[Instruction]) -> bool,
+{
+    let mut current = program.to_vec();
+
+    while let Some(smaller) = candidates(&current).into_iter().find(|c| fails(c)) {
+        current = smaller;
+    }
+
+    current
+}
+
+fn candidates(program: &[Instruction]) -> Vec<Vec<Instruction>> {
+    let mut candidates = Vec::new();
+
+    let mut chunk = program.len();
+    while chunk > 0 {
+        for start in (0..program.len()).step_by(chunk) {
+            let mut smaller = program[..start].to_vec();
+            smaller.extend_from_slice(&program[(start + chunk).min(program.len())..]);
+            candidates.push(smaller);
+        }
+        chunk /= 2;
+    }
+
+    for (index, &instruction) in program.iter().enumerate() {
+        let (argument, rebuild): (isize, fn(isize) -> Instruction) = match instruction {
+            Instruction::Nop(n) => (n, Instruction::Nop),
+            Instruction::Accumulator(a) => (a, Instruction::Accumulator),
+            Instruction::Jump(j) => (j, Instruction::Jump),
+        };
+
+        let mut replacements = Vec::new();
+        if instruction != Instruction::Nop(0) && argument == 0 {
+            replacements.push(Instruction::Nop(0));
+        }
+        if argument != 0 {
+            replacements.push(rebuild(0));
+            replacements.push(rebuild(argument / 2));
+            replacements.push(rebuild(argument - argument.signum()));
+        }
+
+        for replacement in replacements {
+            let mut simpler = program.to_vec();
+            simpler[index] = replacement;
+            candidates.push(simpler);
+        }
+    }
+
+    candidates
+}
+
+// Runs `property` against `cases` random programs and returns a shrunk counterexample if it fails.
+pub fn check<P>(
+    seed: u64,
+    cases: usize,
+    max_len: usize,
+    shape: Shape,
+    property: P,
+) -> Result<(), Vec<Instruction>>
+where
+    P: Fn(&[Instruction]) -> bool,
+{
+    let mut rng = Rng::new(seed);
+
+    for _ in 0..cases {
+        let len = rng.below(max_len + 1);
+        let program = generate(&mut rng, len, shape);
+        if !property(&program) {
+            return Err(shrink(&program, |p| !property(p)));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiled::CompiledProgram;
+    use crate::optimise::optimise;
+    use crate::run;
+    use crate::trace::Trace;
+
+    #[test]
+    fn run_agrees_with_reference_model() {
+        assert_eq!(
+            check(1, 2000, 30, Shape::Any, |p| run(p) == reference::execute(p)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn other_interpreters_agree_with_reference_model() {
+        assert_eq!(
+            check(2, 1000, 30, Shape::Any, |p| {
+                let expected = reference::execute(p);
+                Trace::record(p).result() == expected
+                    && CompiledProgram::new(p).run() == expected
+                    && run(&optimise(p)) == expected
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn generator_respects_shape() {
+        let mut rng = Rng::new(3);
+        for len in 0..40 {
+            assert!(reference::execute(&generate(&mut rng, len, Shape::Terminating)).is_ok());
+            if len > 0 {
+                assert!(reference::execute(&generate(&mut rng, len, Shape::Looping)).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn shrinks_to_minimal_counterexample() {
+        // Deliberately false: claims the accumulator never exceeds 5.
+        let failure = check(4, 1000, 20, Shape::Terminating, |p| {
+            !matches!(reference::execute(p), Ok(acc) if acc > 5)
+        });
+        assert_eq!(failure, Err(vec![Instruction::Accumulator(6)]));
+    }
+}
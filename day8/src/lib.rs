@@ -1,20 +1,15 @@
-use std::error::Error;
+use std::collections::HashSet;
 use std::fmt;
-use std::str::FromStr;
-use scan_fmt::scan_fmt;
 
-#[derive(Debug)]
-pub struct InvalidInstructionError {
-    pub instruction: String,
-}
-
-impl fmt::Display for InvalidInstructionError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Invalid instruction: {}", self.instruction)
-    }
-}
+pub mod bytecode;
+pub mod compiled;
+pub mod fuzz;
+pub mod optimise;
+pub mod parsing;
+pub mod reference;
+pub mod trace;
 
-impl Error for InvalidInstructionError {}
+pub use parsing::{parse_program, ParseError};
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Instruction {
@@ -23,20 +18,42 @@ pub enum Instruction {
     Jump(isize),
 }
 
-impl FromStr for Instruction {
-    type Err = Box<dyn Error>;
-    
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (operation, argument) = scan_fmt!(s, "{} {d}", String, isize)?;
-        match operation.as_str() {
-            "nop" => Ok(Instruction::Nop(argument)),
-            "acc" => Ok(Instruction::Accumulator(argument)),
-            "jmp" => Ok(Instruction::Jump(argument)),
-            unknown => Err(Box::new(InvalidInstructionError { instruction: unknown.to_string() }))
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Nop(n) => write!(f, "nop {:+}", n),
+            Instruction::Accumulator(a) => write!(f, "acc {:+}", a),
+            Instruction::Jump(j) => write!(f, "jmp {:+}", j),
         }
     }
 }
 
+pub fn run(instructions: &[Instruction]) -> Result<isize, isize> {
+    let mut accumulator = 0;
+    let mut pointer = 0;
+    let mut visited = HashSet::new();
+
+    while let Some(instruction) = instructions.get(pointer) {
+        match instruction {
+            Instruction::Accumulator(a) => {
+                accumulator += a;
+                pointer += 1;
+            }
+            Instruction::Jump(j) => {
+                pointer = (pointer as isize + j) as usize;
+            }
+            Instruction::Nop(_) => {
+                pointer += 1;
+            }
+        }
+
+        if !visited.insert(pointer) {
+            return Err(accumulator);
+        }
+    }
+    
+    Ok(accumulator)
+}
 
 #[cfg(test)]
 mod tests {
@@ -62,7 +79,31 @@ mod tests {
 
     #[test]
     fn parse_invalid() {
-        let instruction: Result<Instruction, Box<dyn Error>> = "invalid".parse();
-        assert!(instruction.is_err());
+        let instruction: Result<Instruction, ParseError> = "invalid".parse();
+        assert!(matches!(instruction, Err(ParseError::UnknownOpcode { .. })));
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for text in &["nop +0", "acc +1", "jmp -3", "acc -99"] {
+            let instruction: Instruction = text.parse().unwrap();
+            assert_eq!(&instruction.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_part1() {
+        let instructions = parse_program("nop +0
+        acc +1
+        jmp +4
+        acc +3
+        jmp -3
+        acc -99
+        acc +1
+        jmp -4
+        acc +6").unwrap();
+
+        let acc = run(&instructions);
+        assert_eq!(acc, Err(5));
     }
 }
\ No newline at end of file
@@ -1,27 +1,51 @@
-use day8::Instruction;
-use std::collections::HashSet;
+use day8::trace::Trace;
+use day8::{parse_program, run, Instruction};
+use std::env;
 use std::error::Error;
 
 fn main() -> Result<(), Box<dyn Error>>{
-    let program = include_str!("puzzle_input.txt")
-        .lines()
-        .map(|line| line.parse())
-        .collect::<Result<Vec<Instruction>, Box<dyn Error>>>()?;
-    
+    let program = parse_program(include_str!("puzzle_input.txt"))?;
+
+    match env::args().nth(1).as_deref() {
+        Some("--trace") => {
+            let trace = Trace::record(&program);
+            print!("{}", trace.to_text());
+            print!("{}", trace.coverage(program.len()).to_text(&program));
+            return Ok(());
+        }
+        Some("--trace-json") => {
+            let trace = Trace::record(&program);
+            println!(
+                "{{\"trace\":{},\"coverage\":{}}}",
+                trace.to_json(),
+                trace.coverage(program.len()).to_json()
+            );
+            return Ok(());
+        }
+        _ => {}
+    }
+
     let accumulator = run(&program).unwrap_err();
 
     println!("Part 1: Accumulator = {}", accumulator);
 
+    if let Some((index, acc)) = repair(&program) {
+        println!("Change at line {}. Runs to completion with accumulator = {}.", index, acc);
+    }
+
+    Ok(())
+}
+
+fn repair(program: &[Instruction]) -> Option<(usize, isize)> {
     for index in 0..program.len() {
-        if let Some(mutated) = mutate(&program, index) {
+        if let Some(mutated) = mutate(program, index) {
             if let Ok(acc) = run(&mutated) {
-                println!("Change at line {}. Runs to completion with accumulator = {}.", index, acc);
-                break;
+                return Some((index, acc));
             }
         }
     }
 
-    Ok(())
+    None
 }
 
 fn mutate(program: &[Instruction], index: usize) -> Option<Vec<Instruction>> {
@@ -40,53 +64,31 @@ fn mutate(program: &[Instruction], index: usize) -> Option<Vec<Instruction>> {
     }
 }
 
-fn run(instructions: &[Instruction]) -> Result<isize, isize> {
-    let mut accumulator = 0;
-    let mut pointer = 0;
-    let mut visited = HashSet::new();
-
-    while let Some(instruction) = instructions.get(pointer) {
-        match instruction {
-            Instruction::Accumulator(a) => {
-                accumulator += a;
-                pointer += 1;
-            }
-            Instruction::Jump(j) => {
-                pointer = (pointer as isize + j) as usize;
-            }
-            Instruction::Nop(_) => {
-                pointer += 1;
-            }
-        }
-
-        if !visited.insert(pointer) {
-            return Err(accumulator);
-        }
-    }
-    
-    Ok(accumulator)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use day8::fuzz::{check, Shape};
+    use day8::reference;
 
     #[test]
-    fn test_part1() {
-        let instructions: Vec<Instruction> = "nop +0
-        acc +1
-        jmp +4
-        acc +3
-        jmp -3
-        acc -99
-        acc +1
-        jmp -4
-        acc +6".lines()
-            .map(|line| line.trim().parse())
-            .collect::<Result<Vec<Instruction>, Box<dyn Error>>>()
-            .unwrap();
+    fn repair_agrees_with_reference_model() {
+        for &shape in &[Shape::Any, Shape::Looping] {
+            assert_eq!(
+                check(5, 500, 25, shape, |p| repair(p) == reference::repair(p)),
+                Ok(())
+            );
+        }
+    }
 
-        let acc = run(&instructions);
-        assert_eq!(acc, Err(5));
+    #[test]
+    fn repaired_program_terminates() {
+        let property = |p: &[Instruction]| match repair(p) {
+            Some((index, acc)) => {
+                let repaired = mutate(p, index).unwrap();
+                run(&repaired) == Ok(acc) && reference::execute(&repaired) == Ok(acc)
+            }
+            None => true,
+        };
+        assert_eq!(check(6, 500, 25, Shape::Looping, property), Ok(()));
     }
-}
\ No newline at end of file
+}
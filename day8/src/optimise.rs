@@ -0,0 +1,164 @@
+use crate::Instruction;
+use std::collections::HashSet;
+
+// Peephole optimiser which preserves both the final accumulator and the point at which
+// `run` detects a loop. Only `nop` and `jmp` are skipped by jump threading, since neither
+// changes the accumulator, and `acc` instructions are only merged when nothing jumps
+// into the middle of the run. Instruction 0 is never touched because `run` does not
+// mark it as visited before executing it.
+pub fn optimise(program: &[Instruction]) -> Vec<Instruction> {
+    let len = program.len();
+
+    let resolved: Vec<Option<isize>> = program
+        .iter()
+        .enumerate()
+        .map(|(pc, instruction)| match instruction {
+            Instruction::Jump(j) => Some(resolve(program, (pc as isize).wrapping_add(*j))),
+            _ => None,
+        })
+        .collect();
+
+    let entries: HashSet<usize> = resolved
+        .iter()
+        .flatten()
+        .filter(|&&target| in_range(target, len))
+        .map(|&target| target as usize)
+        .collect();
+
+    // Decide which instructions survive, folding runs of `acc` into their first member.
+    let mut keep = vec![false; len];
+    let mut merged = program.to_vec();
+    let mut last_acc: Option<usize> = None;
+
+    for pc in 0..len {
+        match program[pc] {
+            Instruction::Nop(_) if pc != 0 && !entries.contains(&pc) => continue,
+            Instruction::Accumulator(a) if pc != 0 => match last_acc {
+                Some(previous) if !entries.contains(&pc) => {
+                    if let Instruction::Accumulator(total) = &mut merged[previous] {
+                        *total += a;
+                    }
+                    continue;
+                }
+                _ => last_acc = Some(pc),
+            },
+            _ => last_acc = None,
+        }
+
+        keep[pc] = true;
+    }
+
+    // Map every old position to the position of the next surviving instruction.
+    let mut new_index = vec![0; len + 1];
+    let mut next = keep.iter().filter(|&&k| k).count();
+    new_index[len] = next;
+    for pc in (0..len).rev() {
+        if keep[pc] {
+            next -= 1;
+        }
+        new_index[pc] = next;
+    }
+    let new_len = new_index[len] as isize;
+
+    (0..len)
+        .filter(|&pc| keep[pc])
+        .map(|pc| match merged[pc] {
+            Instruction::Jump(_) => {
+                let target = resolved[pc].unwrap();
+                let new_target = if in_range(target, len) {
+                    new_index[target as usize] as isize
+                } else if target < 0 {
+                    -1
+                } else {
+                    new_len
+                };
+                Instruction::Jump(new_target - new_index[pc] as isize)
+            }
+            instruction => instruction,
+        })
+        .collect()
+}
+
+fn in_range(pc: isize, len: usize) -> bool {
+    pc >= 0 && (pc as usize) < len
+}
+
+// Follows `nop` and `jmp` instructions from `target` until reaching an `acc`, the first
+// instruction, the end of the program or an accumulator-free cycle.
+fn resolve(program: &[Instruction], mut target: isize) -> isize {
+    let mut seen = HashSet::new();
+
+    while in_range(target, program.len()) && target != 0 && seen.insert(target) {
+        target = match program[target as usize] {
+            Instruction::Nop(_) => target + 1,
+            Instruction::Jump(j) => target.wrapping_add(j),
+            Instruction::Accumulator(_) => break,
+        };
+    }
+
+    target
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, run};
+
+    #[test]
+    fn merges_accumulators_and_removes_nops() {
+        let program = parse_program("nop +0\nacc +1\nnop +5\nacc +2\nacc -4\njmp -4").unwrap();
+        let optimised = optimise(&program);
+        assert_eq!(
+            optimised,
+            vec![
+                Instruction::Nop(0),
+                Instruction::Accumulator(-1),
+                Instruction::Jump(-1),
+            ]
+        );
+        assert_eq!(run(&optimised), run(&program));
+    }
+
+    #[test]
+    fn threads_jump_chains() {
+        let program = parse_program("jmp +2\nacc +10\njmp +2\nacc +20\nnop +0\nacc +1").unwrap();
+        let optimised = optimise(&program);
+        assert_eq!(
+            optimised,
+            vec![
+                Instruction::Jump(4),
+                Instruction::Accumulator(10),
+                Instruction::Jump(2),
+                Instruction::Accumulator(20),
+                Instruction::Accumulator(1),
+            ]
+        );
+        assert_eq!(run(&optimised), run(&program));
+    }
+
+    #[test]
+    fn keeps_accumulator_free_cycles() {
+        let program = parse_program("acc +3\nnop +0\njmp -1").unwrap();
+        let optimised = optimise(&program);
+        assert_eq!(run(&optimised), run(&program));
+    }
+
+    #[test]
+    fn preserves_results_for_every_repair_of_puzzle_input() {
+        let program = parse_program(include_str!("puzzle_input.txt")).unwrap();
+        assert_eq!(run(&optimise(&program)), run(&program));
+
+        for index in 0..program.len() {
+            let mut mutated = program.clone();
+            mutated[index] = match program[index] {
+                Instruction::Nop(n) => Instruction::Jump(n),
+                Instruction::Jump(n) => Instruction::Nop(n),
+                Instruction::Accumulator(_) => continue,
+            };
+
+            let optimised = optimise(&mutated);
+            assert!(optimised.len() <= mutated.len());
+            assert_eq!(run(&optimised), run(&mutated), "mutation at {}", index);
+        }
+    }
+}
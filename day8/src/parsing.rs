@@ -0,0 +1,299 @@
+use crate::Instruction;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+pub type Span = Range<usize>;
+
+// Lines are numbered from 1, and are `None` when a single instruction was parsed on its
+// own; spans are byte offsets within the line.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ParseError {
+    UnknownOpcode {
+        line: Option<usize>,
+        span: Span,
+        opcode: String,
+    },
+    MissingArgument {
+        line: Option<usize>,
+        span: Span,
+    },
+    BadArgument {
+        line: Option<usize>,
+        span: Span,
+        argument: String,
+    },
+    TrailingInput {
+        line: Option<usize>,
+        span: Span,
+    },
+    BlankLine {
+        line: Option<usize>,
+        span: Span,
+    },
+}
+
+impl ParseError {
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            ParseError::UnknownOpcode { line, .. }
+            | ParseError::MissingArgument { line, .. }
+            | ParseError::BadArgument { line, .. }
+            | ParseError::TrailingInput { line, .. }
+            | ParseError::BlankLine { line, .. } => *line,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnknownOpcode { span, .. }
+            | ParseError::MissingArgument { span, .. }
+            | ParseError::BadArgument { span, .. }
+            | ParseError::TrailingInput { span, .. }
+            | ParseError::BlankLine { span, .. } => span.clone(),
+        }
+    }
+
+    fn at_line(self, line: usize) -> Self {
+        let line = Some(line);
+        match self {
+            ParseError::UnknownOpcode { span, opcode, .. } => {
+                ParseError::UnknownOpcode { line, span, opcode }
+            }
+            ParseError::MissingArgument { span, .. } => ParseError::MissingArgument { line, span },
+            ParseError::BadArgument { span, argument, .. } => {
+                ParseError::BadArgument { line, span, argument }
+            }
+            ParseError::TrailingInput { span, .. } => ParseError::TrailingInput { line, span },
+            ParseError::BlankLine { span, .. } => ParseError::BlankLine { line, span },
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let span = self.span();
+        if let Some(line) = self.line() {
+            write!(f, "line {}, ", line)?;
+        }
+        if span.len() > 1 {
+            write!(f, "columns {}-{}: ", span.start + 1, span.end)?;
+        } else {
+            write!(f, "column {}: ", span.start + 1)?;
+        }
+        match self {
+            ParseError::UnknownOpcode { opcode, .. } => write!(f, "unknown opcode \"{}\"", opcode),
+            ParseError::MissingArgument { .. } => write!(f, "missing argument"),
+            ParseError::BadArgument { argument, .. } => {
+                write!(f, "invalid argument \"{}\"", argument)
+            }
+            ParseError::TrailingInput { .. } => write!(f, "unexpected input after the argument"),
+            ParseError::BlankLine { .. } => write!(f, "blank line between instructions"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseErrors(pub Vec<ParseError>);
+
+impl fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ParseErrors {}
+
+impl FromStr for Instruction {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = tokens(s);
+
+        let (opcode_span, opcode) = match tokens.next() {
+            Some(token) => token,
+            None => {
+                return Err(ParseError::UnknownOpcode {
+                    line: None,
+                    span: s.len()..s.len(),
+                    opcode: String::new(),
+                })
+            }
+        };
+
+        let constructor = match opcode {
+            "nop" => Instruction::Nop,
+            "acc" => Instruction::Accumulator,
+            "jmp" => Instruction::Jump,
+            unknown => {
+                return Err(ParseError::UnknownOpcode {
+                    line: None,
+                    span: opcode_span,
+                    opcode: unknown.to_string(),
+                })
+            }
+        };
+
+        let (argument_span, argument) = tokens.next().ok_or(ParseError::MissingArgument {
+            line: None,
+            span: opcode_span.end..opcode_span.end,
+        })?;
+
+        let value = argument.parse().map_err(|_| ParseError::BadArgument {
+            line: None,
+            span: argument_span.clone(),
+            argument: argument.to_string(),
+        })?;
+
+        if let Some((trailing, _)) = tokens.next() {
+            return Err(ParseError::TrailingInput {
+                line: None,
+                span: trailing.start..s.trim_end().len(),
+            });
+        }
+
+        Ok(constructor(value))
+    }
+}
+
+// Parses a whole program, reporting every bad line rather than just the first. Jumps are
+// relative to line numbers, so blank lines between instructions are errors; only blank
+// lines after the last instruction are ignored.
+pub fn parse_program(input: &str) -> Result<Vec<Instruction>, ParseErrors> {
+    let mut program = Vec::new();
+    let mut errors = Vec::new();
+    let lines: Vec<&str> = input.lines().collect();
+    let end = lines
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .map_or(0, |last| last + 1);
+
+    for (index, line) in lines[..end].iter().enumerate() {
+        if line.trim().is_empty() {
+            errors.push(ParseError::BlankLine {
+                line: Some(index + 1),
+                span: 0..line.len(),
+            });
+            continue;
+        }
+
+        match line.parse::<Instruction>() {
+            Ok(instruction) => program.push(instruction),
+            Err(error) => errors.push(error.at_line(index + 1)),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(ParseErrors(errors))
+    }
+}
+
+fn tokens(line: &str) -> impl Iterator<Item = (Span, &str)> {
+    line.split_whitespace().map(move |token| {
+        let start = token.as_ptr() as usize - line.as_ptr() as usize;
+        (start..start + token.len(), token)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_opcode() {
+        assert_eq!(
+            "  xyz +1".parse::<Instruction>(),
+            Err(ParseError::UnknownOpcode {
+                line: None,
+                span: 2..5,
+                opcode: "xyz".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn missing_argument() {
+        assert_eq!(
+            "jmp".parse::<Instruction>(),
+            Err(ParseError::MissingArgument { line: None, span: 3..3 })
+        );
+    }
+
+    #[test]
+    fn bad_argument() {
+        assert_eq!(
+            "acc +x1".parse::<Instruction>(),
+            Err(ParseError::BadArgument {
+                line: None,
+                span: 4..7,
+                argument: "+x1".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn trailing_input() {
+        assert_eq!(
+            "nop +0 and more ".parse::<Instruction>(),
+            Err(ParseError::TrailingInput { line: None, span: 7..15 })
+        );
+    }
+
+    #[test]
+    fn program_collects_every_error() {
+        let errors = parse_program("nop +0\nfoo +1\n\nacc\njmp +2 +3\nacc +4").unwrap_err();
+        let lines: Vec<Option<usize>> = errors.0.iter().map(|e| e.line()).collect();
+        assert_eq!(lines, vec![Some(2), Some(3), Some(4), Some(5)]);
+        assert_eq!(
+            errors.to_string(),
+            "line 2, columns 1-3: unknown opcode \"foo\"\n\
+             line 3, column 1: blank line between instructions\n\
+             line 4, column 4: missing argument\n\
+             line 5, columns 8-9: unexpected input after the argument"
+        );
+    }
+
+    #[test]
+    fn program_parses_valid_input() {
+        let program = parse_program("nop +0\n  acc -7  \njmp +1\n\n  \n").unwrap();
+        assert_eq!(
+            program,
+            vec![
+                Instruction::Nop(0),
+                Instruction::Accumulator(-7),
+                Instruction::Jump(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn single_instructions_have_no_line() {
+        let error = "jmp".parse::<Instruction>().unwrap_err();
+        assert_eq!(error.line(), None);
+        assert_eq!(error.to_string(), "column 4: missing argument");
+    }
+
+    #[test]
+    fn blank_lines_would_move_jumps() {
+        // The jmp targets the acc on line 3 only while the blank line isn't skipped.
+        let errors = parse_program("jmp +2\n\nacc +1").unwrap_err();
+        assert_eq!(
+            errors.0,
+            vec![ParseError::BlankLine {
+                line: Some(2),
+                span: 0..0
+            }]
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use crate::Instruction;
+
+// A deliberately simple model of the console, kept separate from `run` so the two can be
+// checked against each other. It records the full history of program counters and stops
+// at the first one that has appeared before. The starting position is not part of the
+// history, matching `run`, which only marks an instruction as visited when control arrives at it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct State {
+    pub pc: usize,
+    pub acc: isize,
+}
+
+pub fn step(program: &[Instruction], state: State) -> Option<State> {
+    let instruction = program.get(state.pc)?;
+    let pc = state.pc as isize;
+
+    let (pc, acc) = match *instruction {
+        Instruction::Nop(_) => (pc + 1, state.acc),
+        Instruction::Accumulator(a) => (pc + 1, state.acc + a),
+        Instruction::Jump(j) => (pc + j, state.acc),
+    };
+
+    if pc < 0 {
+        None
+    } else {
+        Some(State {
+            pc: pc as usize,
+            acc,
+        })
+    }
+}
+
+pub fn execute(program: &[Instruction]) -> Result<isize, isize> {
+    let mut state = State { pc: 0, acc: 0 };
+    let mut history: Vec<usize> = Vec::new();
+
+    loop {
+        match step(program, state) {
+            None => return Ok(state.acc),
+            Some(next) if history.contains(&next.pc) => return Err(next.acc),
+            Some(next) => {
+                history.push(next.pc);
+                state = next;
+            }
+        }
+    }
+}
+
+pub fn repair(program: &[Instruction]) -> Option<(usize, isize)> {
+    (0..program.len()).find_map(|index| {
+        let mut candidate = program.to_vec();
+        candidate[index] = match program[index] {
+            Instruction::Nop(n) => Instruction::Jump(n),
+            Instruction::Jump(n) => Instruction::Nop(n),
+            Instruction::Accumulator(_) => return None,
+        };
+        execute(&candidate).ok().map(|acc| (index, acc))
+    })
+}
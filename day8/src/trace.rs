@@ -0,0 +1,322 @@
+use crate::Instruction;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Step {
+    pub pc: usize,
+    pub instruction: Instruction,
+    pub acc_before: isize,
+    pub acc_after: isize,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Outcome {
+    Terminated(isize),
+    Looped { accumulator: isize, entry: usize },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Loop {
+    pub entry: usize,
+    pub body: Vec<usize>,
+    pub acc_delta: isize,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Coverage {
+    pub executed: Vec<usize>,
+    pub unexecuted: Vec<usize>,
+    pub hot_loop: Option<Loop>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+    pub outcome: Outcome,
+}
+
+impl Trace {
+    // Follows exactly the same rules as `run`, so the outcome always agrees with it.
+    pub fn record(instructions: &[Instruction]) -> Trace {
+        let mut accumulator = 0;
+        let mut pointer = 0;
+        let mut visited = HashSet::new();
+        let mut steps = Vec::new();
+
+        while let Some(&instruction) = instructions.get(pointer) {
+            let acc_before = accumulator;
+            let pc = pointer;
+
+            match instruction {
+                Instruction::Accumulator(a) => {
+                    accumulator += a;
+                    pointer += 1;
+                }
+                Instruction::Jump(j) => {
+                    pointer = (pointer as isize + j) as usize;
+                }
+                Instruction::Nop(_) => {
+                    pointer += 1;
+                }
+            }
+
+            steps.push(Step {
+                pc,
+                instruction,
+                acc_before,
+                acc_after: accumulator,
+            });
+
+            if !visited.insert(pointer) {
+                return Trace {
+                    steps,
+                    outcome: Outcome::Looped {
+                        accumulator,
+                        entry: pointer,
+                    },
+                };
+            }
+        }
+
+        Trace {
+            steps,
+            outcome: Outcome::Terminated(accumulator),
+        }
+    }
+
+    pub fn result(&self) -> Result<isize, isize> {
+        match self.outcome {
+            Outcome::Terminated(acc) => Ok(acc),
+            Outcome::Looped { accumulator, .. } => Err(accumulator),
+        }
+    }
+
+    pub fn coverage(&self, program_len: usize) -> Coverage {
+        let mut hit = vec![false; program_len];
+        for step in &self.steps {
+            hit[step.pc] = true;
+        }
+
+        let executed = (0..program_len).filter(|&pc| hit[pc]).collect();
+        let unexecuted = (0..program_len).filter(|&pc| !hit[pc]).collect();
+
+        let hot_loop = match self.outcome {
+            Outcome::Looped { entry, .. } => {
+                // The loop starts where control first arrived at the repeated instruction.
+                let start = self
+                    .steps
+                    .iter()
+                    .skip(1)
+                    .position(|step| step.pc == entry)
+                    .map_or(0, |i| i + 1);
+                let body = &self.steps[start..];
+                Some(Loop {
+                    entry,
+                    body: body.iter().map(|step| step.pc).collect(),
+                    acc_delta: body.iter().map(|step| step.acc_after - step.acc_before).sum(),
+                })
+            }
+            Outcome::Terminated(_) => None,
+        };
+
+        Coverage {
+            executed,
+            unexecuted,
+            hot_loop,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for step in &self.steps {
+            writeln!(
+                text,
+                "{:>5}  {}  acc {} -> {}",
+                step.pc, step.instruction, step.acc_before, step.acc_after
+            )
+            .unwrap();
+        }
+
+        match self.outcome {
+            Outcome::Terminated(acc) => writeln!(text, "terminated with acc = {}", acc),
+            Outcome::Looped { accumulator, entry } => writeln!(
+                text,
+                "loop detected at {} with acc = {}",
+                entry, accumulator
+            ),
+        }
+        .unwrap();
+
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        let steps = self
+            .steps
+            .iter()
+            .map(|step| {
+                format!(
+                    "{{\"pc\":{},\"instruction\":\"{}\",\"acc_before\":{},\"acc_after\":{}}}",
+                    step.pc, step.instruction, step.acc_before, step.acc_after
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let outcome = match self.outcome {
+            Outcome::Terminated(acc) => {
+                format!("{{\"terminated\":true,\"accumulator\":{}}}", acc)
+            }
+            Outcome::Looped { accumulator, entry } => format!(
+                "{{\"terminated\":false,\"accumulator\":{},\"entry\":{}}}",
+                accumulator, entry
+            ),
+        };
+
+        format!("{{\"steps\":[{}],\"outcome\":{}}}", steps, outcome)
+    }
+}
+
+impl Coverage {
+    pub fn to_text(&self, instructions: &[Instruction]) -> String {
+        let mut text = String::new();
+        writeln!(
+            text,
+            "{} of {} instructions executed",
+            self.executed.len(),
+            instructions.len()
+        )
+        .unwrap();
+
+        writeln!(text, "unexecuted:").unwrap();
+        for &pc in &self.unexecuted {
+            writeln!(text, "{:>5}  {}", pc, instructions[pc]).unwrap();
+        }
+
+        if let Some(hot_loop) = &self.hot_loop {
+            writeln!(
+                text,
+                "hot loop entered at {}: {} instructions, acc {:+} per iteration",
+                hot_loop.entry,
+                hot_loop.body.len(),
+                hot_loop.acc_delta
+            )
+            .unwrap();
+            for &pc in &hot_loop.body {
+                writeln!(text, "{:>5}  {}", pc, instructions[pc]).unwrap();
+            }
+        }
+
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        let hot_loop = match &self.hot_loop {
+            Some(hot_loop) => format!(
+                "{{\"entry\":{},\"body\":{},\"acc_delta\":{}}}",
+                hot_loop.entry,
+                json_list(&hot_loop.body),
+                hot_loop.acc_delta
+            ),
+            None => "null".to_string(),
+        };
+
+        format!(
+            "{{\"executed\":{},\"unexecuted\":{},\"hot_loop\":{}}}",
+            json_list(&self.executed),
+            json_list(&self.unexecuted),
+            hot_loop
+        )
+    }
+}
+
+fn json_list(values: &[usize]) -> String {
+    let items = values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("[{}]", items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run;
+
+    fn example() -> Vec<Instruction> {
+        "nop +0
+        acc +1
+        jmp +4
+        acc +3
+        jmp -3
+        acc -99
+        acc +1
+        jmp -4
+        acc +6"
+            .lines()
+            .map(|line| line.trim().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn trace_records_every_step() {
+        let program = example();
+        let trace = Trace::record(&program);
+        let pcs: Vec<usize> = trace.steps.iter().map(|step| step.pc).collect();
+        assert_eq!(pcs, vec![0, 1, 2, 6, 7, 3, 4]);
+        assert_eq!(
+            trace.steps[1],
+            Step {
+                pc: 1,
+                instruction: Instruction::Accumulator(1),
+                acc_before: 0,
+                acc_after: 1
+            }
+        );
+        assert_eq!(trace.result(), run(&program));
+    }
+
+    #[test]
+    fn coverage_of_looping_program() {
+        let program = example();
+        let coverage = Trace::record(&program).coverage(program.len());
+        assert_eq!(coverage.unexecuted, vec![5, 8]);
+        assert_eq!(
+            coverage.hot_loop,
+            Some(Loop {
+                entry: 1,
+                body: vec![1, 2, 6, 7, 3, 4],
+                acc_delta: 5
+            })
+        );
+    }
+
+    #[test]
+    fn coverage_of_terminating_program() {
+        let mut program = example();
+        program[7] = Instruction::Nop(-4);
+        let trace = Trace::record(&program);
+        assert_eq!(trace.outcome, Outcome::Terminated(8));
+        assert_eq!(trace.coverage(program.len()).hot_loop, None);
+    }
+
+    #[test]
+    fn json_output() {
+        let program = vec![Instruction::Accumulator(2), Instruction::Jump(-1)];
+        let trace = Trace::record(&program);
+        assert_eq!(
+            trace.to_json(),
+            "{\"steps\":[\
+             {\"pc\":0,\"instruction\":\"acc +2\",\"acc_before\":0,\"acc_after\":2},\
+             {\"pc\":1,\"instruction\":\"jmp -1\",\"acc_before\":2,\"acc_after\":2},\
+             {\"pc\":0,\"instruction\":\"acc +2\",\"acc_before\":2,\"acc_after\":4}],\
+             \"outcome\":{\"terminated\":false,\"accumulator\":4,\"entry\":1}}"
+        );
+        assert_eq!(
+            trace.coverage(program.len()).to_json(),
+            "{\"executed\":[0,1],\"unexecuted\":[],\"hot_loop\":{\"entry\":1,\"body\":[1,0],\"acc_delta\":2}}"
+        );
+    }
+}